@@ -0,0 +1,288 @@
+use crate::{
+    builder::Config,
+    chunks::{chunks, write_chunk, Chunk, PNG_SIGNATURE},
+    decode::{alloc_pixels, decode_header, decode_png_with_config, flip_rows},
+    PngLoadError,
+};
+use egui::{Color32, ColorImage};
+use std::{mem::size_of, time::Duration};
+
+/// The frames of an animated PNG, fully composited and ready to be displayed in order.
+///
+/// Static PNGs are represented as a single frame with a zero delay.
+#[derive(Clone)]
+pub struct AnimatedPng {
+    /// Each frame along with how long it should be displayed for.
+    pub frames: Vec<(ColorImage, Duration)>,
+
+    /// The number of times the animation should be played, or 0 to loop forever.
+    pub loop_count: u32,
+}
+
+/// How long [`AnimatedPng::frame_at`] shows frames with a zero delay for, so that they aren't skipped entirely.
+const MIN_FRAME_DELAY: Duration = Duration::from_millis(10);
+
+impl AnimatedPng {
+    /// Returns the index of the frame to show `elapsed` after the animation started playing.
+    ///
+    /// Frames are shown for their delays one after another, looping back to the first frame after the last one
+    /// until the animation has played [`Self::loop_count`] times, after which the last frame stays. Zero delays are
+    /// treated as a minimal one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no [frames](Self::frames).
+    pub fn frame_index_at(&self, elapsed: Duration) -> usize {
+        assert!(!self.frames.is_empty(), "animation has no frames");
+        let delays = || self.frames.iter().map(|(_, delay)| delay.max(&MIN_FRAME_DELAY).as_nanos());
+        let total: u128 = delays().sum();
+        let elapsed = elapsed.as_nanos();
+        if self.loop_count != 0 && elapsed / total >= u128::from(self.loop_count) {
+            return self.frames.len() - 1;
+        }
+
+        let mut into_loop = elapsed % total;
+        for (index, delay) in delays().enumerate() {
+            if into_loop < delay {
+                return index;
+            }
+            into_loop -= delay;
+        }
+        unreachable!("the time into a loop is shorter than the loop")
+    }
+
+    /// Returns the frame to show `elapsed` after the animation started playing, as chosen by
+    /// [`Self::frame_index_at`].
+    ///
+    /// To animate the frames, keep the time the animation started at and request a repaint for when the frame
+    /// changes, or on every frame.
+    pub fn frame_at(&self, elapsed: Duration) -> &ColorImage {
+        &self.frames[self.frame_index_at(elapsed)].0
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DisposeOp {
+    None,
+    Background,
+    Previous,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum BlendOp {
+    Source,
+    Over,
+}
+
+/// The contents of an `fcTL` chunk.
+struct FrameControl {
+    width: usize,
+    height: usize,
+    x_offset: usize,
+    y_offset: usize,
+    delay: Duration,
+    dispose_op: DisposeOp,
+    blend_op: BlendOp,
+}
+
+impl FrameControl {
+    fn parse(data: &[u8]) -> Result<Self, PngLoadError> {
+        if data.len() != 26 {
+            return Err(PngLoadError::InvalidAnimation("bad fcTL chunk size"));
+        }
+
+        let u32_at = |i: usize| u32::from_be_bytes(data[i..i + 4].try_into().unwrap()) as usize;
+        let delay_num = u16::from_be_bytes([data[20], data[21]]);
+        let delay_den = match u16::from_be_bytes([data[22], data[23]]) {
+            0 => 100,
+            den => den,
+        };
+
+        Ok(Self {
+            width: u32_at(4),
+            height: u32_at(8),
+            x_offset: u32_at(12),
+            y_offset: u32_at(16),
+            delay: Duration::from_secs_f64(f64::from(delay_num) / f64::from(delay_den)),
+            dispose_op: match data[24] {
+                0 => DisposeOp::None,
+                1 => DisposeOp::Background,
+                2 => DisposeOp::Previous,
+                _ => return Err(PngLoadError::InvalidAnimation("bad dispose op")),
+            },
+            blend_op: match data[25] {
+                0 => BlendOp::Source,
+                1 => BlendOp::Over,
+                _ => return Err(PngLoadError::InvalidAnimation("bad blend op")),
+            },
+        })
+    }
+}
+
+/// Reassembles a single frame into a standalone PNG so that minipng can decode it.
+fn frame_to_png(ihdr: &[u8], shared: &[Chunk], control: &FrameControl, data: &[&[u8]]) -> Vec<u8> {
+    let mut ihdr = ihdr.to_vec();
+    ihdr[0..4].copy_from_slice(&(control.width as u32).to_be_bytes());
+    ihdr[4..8].copy_from_slice(&(control.height as u32).to_be_bytes());
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &ihdr);
+    for chunk in shared {
+        write_chunk(&mut png, &chunk.kind, chunk.data);
+    }
+    for data in data {
+        write_chunk(&mut png, b"IDAT", data);
+    }
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+/// Composites premultiplied `src` over `dst`.
+fn blend_over(dst: Color32, src: Color32) -> Color32 {
+    let inv_alpha = 255 - u16::from(src.a());
+    let blend = |s: u8, d: u8| s.saturating_add(((u16::from(d) * inv_alpha + 127) / 255) as u8);
+    Color32::from_rgba_premultiplied(
+        blend(src.r(), dst.r()),
+        blend(src.g(), dst.g()),
+        blend(src.b(), dst.b()),
+        blend(src.a(), dst.a()),
+    )
+}
+
+/// Decodes every frame of an animated PNG.
+///
+/// PNGs without an `acTL` chunk are decoded as a single static frame.
+pub(crate) fn decode_animated(bytes: &[u8], config: &Config) -> Result<AnimatedPng, PngLoadError> {
+    let mut animation = composite_frames(bytes, config)?;
+    for (frame, _) in &mut animation.frames {
+        if let Some(background) = config.flatten_over.map(Color32::to_opaque) {
+            frame.pixels.iter_mut().for_each(|pixel| *pixel = blend_over(background, *pixel));
+        }
+        if config.flip_vertically {
+            flip_rows(&mut frame.pixels, frame.size[0]);
+        }
+    }
+    Ok(animation)
+}
+
+/// Checks that `frames` frames of `width` by `height` pixels fit within the limits of `config`, taken together.
+///
+/// Every frame is kept as a whole image, so tiny frames on a large canvas still take up the size of the canvas each.
+/// Together they are held to the loader's byte limits, and to as many pixels as a single image of the maximum
+/// dimensions would have.
+fn check_frames_size(width: usize, height: usize, frames: usize, config: &Config) -> Result<(), PngLoadError> {
+    let too_large = PngLoadError::TooLarge { width: width as u32, height: height as u32, max_dimensions: None };
+    let Some(pixels) = width.checked_mul(height).and_then(|pixels| pixels.checked_mul(frames)) else {
+        return Err(too_large);
+    };
+    let Some(bytes) = pixels.checked_mul(size_of::<Color32>()) else {
+        return Err(too_large);
+    };
+
+    let max_pixels = config.max_dimensions.map(|(max_width, max_height)| max_width as u64 * max_height as u64);
+    let max_bytes = config.max_entry_bytes.into_iter().chain(config.max_cache_bytes).min();
+    if max_pixels.is_some_and(|max_pixels| pixels as u64 > max_pixels) || max_bytes.is_some_and(|max| bytes > max) {
+        return Err(too_large);
+    }
+    Ok(())
+}
+
+/// Copies `canvas` into a new frame, failing instead of aborting if there is no memory left for it.
+fn snapshot(canvas: &ColorImage) -> Result<ColorImage, PngLoadError> {
+    let mut pixels = alloc_pixels(0)?;
+    pixels
+        .try_reserve_exact(canvas.pixels.len())
+        .map_err(|_| PngLoadError::Allocation { bytes: canvas.pixels.len() * size_of::<Color32>() })?;
+    pixels.extend_from_slice(&canvas.pixels);
+    Ok(ColorImage { size: canvas.size, pixels })
+}
+
+fn composite_frames(bytes: &[u8], config: &Config) -> Result<AnimatedPng, PngLoadError> {
+    // Frames are blended over each other, so they are only flattened once they are complete
+    let frame_config = Config { flatten_over: None, ..config.clone() };
+    let mut ihdr = None;
+    let mut loop_count = None;
+    let mut shared = Vec::new();
+    let mut frames: Vec<(FrameControl, Vec<&[u8]>)> = Vec::new();
+    let mut seen_idat = false;
+
+    for chunk in chunks(bytes)? {
+        let chunk = chunk?;
+        match &chunk.kind {
+            b"IHDR" => ihdr = Some(chunk.data),
+            b"acTL" if chunk.data.len() == 8 => {
+                loop_count = Some(u32::from_be_bytes(chunk.data[4..8].try_into().unwrap()));
+            }
+            // Gamma chunks are shared too, so that every frame gets the same gamma correction as the default image
+            b"PLTE" | b"tRNS" | b"gAMA" | b"sRGB" if !seen_idat => shared.push(chunk),
+            b"fcTL" => frames.push((FrameControl::parse(chunk.data)?, Vec::new())),
+            b"IDAT" => {
+                // The default image is only part of the animation if an fcTL chunk precedes it
+                seen_idat = true;
+                if let Some((_, data)) = frames.last_mut() {
+                    data.push(chunk.data);
+                }
+            }
+            b"fdAT" => {
+                let (Some((_, data)), Some(frame_data)) = (frames.last_mut(), chunk.data.get(4..)) else {
+                    return Err(PngLoadError::InvalidAnimation("fdAT chunk without a preceding fcTL"));
+                };
+                data.push(frame_data);
+            }
+            _ => {}
+        }
+    }
+
+    let (Some(ihdr), Some(loop_count), false) = (ihdr.filter(|ihdr| ihdr.len() == 13), loop_count, frames.is_empty())
+    else {
+        let frame = decode_png_with_config(bytes, &frame_config)?;
+        return Ok(AnimatedPng { frames: vec![(frame, Duration::ZERO)], loop_count: 0 });
+    };
+
+    let header = decode_header(bytes)?;
+    let [width, height] = [header.width() as usize, header.height() as usize];
+    check_frames_size(width, height, frames.len(), config)?;
+    let mut canvas = ColorImage::new([width, height], Color32::TRANSPARENT);
+    let mut output = Vec::with_capacity(frames.len());
+
+    for (i, (control, data)) in frames.iter().enumerate() {
+        if control.x_offset + control.width > width || control.y_offset + control.height > height {
+            return Err(PngLoadError::InvalidAnimation("frame lies outside of the image"));
+        }
+
+        let frame = decode_png_with_config(&frame_to_png(ihdr, &shared, control, data), &frame_config)?;
+        let rows = (control.y_offset..control.y_offset + control.height).map(|y| {
+            let start = y * width + control.x_offset;
+            start..start + control.width
+        });
+
+        // The first frame can't be restored to a previous state, so it is cleared instead
+        let dispose_op = match control.dispose_op {
+            DisposeOp::Previous if i == 0 => DisposeOp::Background,
+            dispose_op => dispose_op,
+        };
+        let previous = (dispose_op == DisposeOp::Previous).then(|| canvas.clone());
+
+        for (row, src_row) in rows.clone().zip(frame.pixels.chunks_exact(control.width.max(1))) {
+            for (dst, &src) in canvas.pixels[row].iter_mut().zip(src_row) {
+                *dst = match control.blend_op {
+                    BlendOp::Source => src,
+                    BlendOp::Over => blend_over(*dst, src),
+                };
+            }
+        }
+        output.push((snapshot(&canvas)?, control.delay));
+
+        match dispose_op {
+            DisposeOp::None => {}
+            DisposeOp::Background => {
+                for row in rows {
+                    canvas.pixels[row].fill(Color32::TRANSPARENT);
+                }
+            }
+            DisposeOp::Previous => canvas = previous.unwrap(),
+        }
+    }
+
+    Ok(AnimatedPng { frames: output, loop_count })
+}
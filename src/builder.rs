@@ -0,0 +1,703 @@
+use crate::{
+    cache::{CacheLimits, Key},
+    chunks::find_bad_crc,
+    uri::{normalize_uri, split_rect_fragment},
+    Decoder, PngLoadError, PngLoader,
+};
+use egui::{
+    ahash::{HashMap, HashSet},
+    Color32, ColorImage, Context, Id, TextureOptions,
+};
+use std::{hash::BuildHasher, mem::size_of, sync::Arc, time::Duration};
+
+pub(crate) type MimePredicate = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+pub(crate) type ErrorCallback = Arc<dyn Fn(&str, &PngLoadError) + Send + Sync>;
+pub(crate) type EvictCallback = Arc<dyn Fn(&str) + Send + Sync>;
+pub(crate) type Spawner = Arc<dyn Fn(Box<dyn FnOnce() + Send>) + Send + Sync>;
+
+/// What to do with images that are larger than the renderer's maximum texture size.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OversizedTexture {
+    /// Fail with [`PngLoadError::TooLargeForTexture`](crate::PngLoadError::TooLargeForTexture).
+    #[default]
+    Reject,
+
+    /// Downscale the image until it fits.
+    Downscale,
+}
+
+/// What to do with images that minipng rejects because of a chunk it can't make sense of, although the pixels
+/// themselves may be fine.
+///
+/// This covers [`MinipngError::BadTrnsChunk`](crate::MinipngError::BadTrnsChunk), for transparency chunks that don't
+/// match the palette, and [`MinipngError::UnrecognizedChunk`](crate::MinipngError::UnrecognizedChunk), for critical
+/// chunks from extensions of the format. Unknown color types and bit depths are reported as
+/// [`MinipngError::BadIhdr`](crate::MinipngError::BadIhdr), and interlaced images as
+/// [`PngLoadError::Interlaced`](crate::PngLoadError::Interlaced), which leave nothing to recover, so they always fail.
+/// Failed images can be replaced by a placeholder with [`PngLoaderBuilder::fallback`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BrokenImages {
+    /// Fail with the error minipng reported.
+    #[default]
+    Reject,
+
+    /// Decode the image again from its header, palette and pixel data alone, dropping every other chunk.
+    ///
+    /// This loses whatever the dropped chunks held, such as transparency and gamma, so the image may not look as
+    /// intended.
+    BestEffort,
+}
+
+/// How images are reduced to a smaller size, when a [`SizeHint`](egui::load::SizeHint) or the renderer's maximum
+/// texture size asks for one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DownsampleFilter {
+    /// Keep the source pixel nearest to the center of each output pixel.
+    ///
+    /// This is the fastest filter and keeps hard edges, such as those of line art and pixel art, sharp, but thin
+    /// details can disappear and fine patterns alias.
+    Nearest,
+
+    /// Average every source pixel that an output pixel covers.
+    ///
+    /// This is smooth and free of aliasing, which suits photos and thumbnails, but blurs hard edges. It is slower
+    /// than [`Self::Nearest`], as every source pixel is read.
+    #[default]
+    Box,
+}
+
+/// Settings shared by a [`PngLoaderBuilder`] and the [`PngLoader`] it builds.
+#[derive(Clone)]
+pub(crate) struct Config {
+    pub max_dimensions: Option<(u32, u32)>,
+    pub max_cache_bytes: Option<usize>,
+    pub max_cache_entries: Option<usize>,
+    pub max_entry_bytes: Option<usize>,
+    pub max_decoded_bytes_per_load: Option<usize>,
+    pub lazy_decoding: Option<u64>,
+    pub sniff_content: bool,
+    pub preserve_16_bit: bool,
+    pub dither_16_bit: bool,
+    pub gamma_correction: bool,
+    pub premultiply_alpha: bool,
+    pub extensions: HashSet<String>,
+    pub mime_predicate: Option<MimePredicate>,
+    pub strict_mime: bool,
+    pub cache_enabled: bool,
+    pub max_texture_side: Option<usize>,
+    pub oversized_textures: OversizedTexture,
+    pub broken_images: BrokenImages,
+    pub background_decoding: bool,
+    pub spawner: Option<Spawner>,
+    pub max_concurrent_decodes: usize,
+    pub decode_retries: u32,
+    pub decode_retry_backoff: Duration,
+    pub reuse_buffers: bool,
+    pub flip_vertically: bool,
+    pub flatten_over: Option<Color32>,
+    pub cache_capacity: usize,
+    pub on_error: Option<ErrorCallback>,
+    pub on_evict: Option<EvictCallback>,
+    pub detect_changes: bool,
+    pub compact_palettes: bool,
+    pub repaint_while_pending: bool,
+    pub byte_error_backoff: Duration,
+    pub fallback: Option<Arc<ColorImage>>,
+    pub texture_options: HashMap<String, TextureOptions>,
+    pub nearest_filter_max_side: Option<u32>,
+    pub verify_crc: bool,
+    pub downsample_filter: DownsampleFilter,
+    pub downsample_filters: HashMap<String, DownsampleFilter>,
+    pub decoder: Option<Arc<dyn Decoder>>,
+}
+
+impl Config {
+    /// Returns the filter to downsample the image at `uri` with, falling back to that of its sprite sheet for parts
+    /// of one.
+    pub fn downsample_filter_for(&self, uri: &str) -> DownsampleFilter {
+        let source = split_rect_fragment(uri).map_or(uri, |(source, _)| source);
+        let filters = &self.downsample_filters;
+        let filter = filters.get(&*normalize_uri(uri)).or_else(|| filters.get(&*normalize_uri(source)));
+        filter.copied().unwrap_or(self.downsample_filter)
+    }
+
+    pub fn check_dimensions(&self, width: u32, height: u32) -> Result<(), PngLoadError> {
+        match self.max_dimensions {
+            Some((max_width, max_height)) if width > max_width || height > max_height => {
+                Err(PngLoadError::TooLarge { width, height, max_dimensions: Some((max_width, max_height)) })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn cache_limits(&self) -> CacheLimits {
+        CacheLimits { max_bytes: self.max_cache_bytes, max_entries: self.max_cache_entries }
+    }
+
+    /// Returns whether an image of `size` is small enough to be cached, per [`PngLoaderBuilder::max_entry_bytes`].
+    pub fn admits(&self, size: [usize; 2]) -> bool {
+        self.max_entry_bytes
+            .map_or(true, |max_bytes| size[0].saturating_mul(size[1]) * size_of::<Color32>() <= max_bytes)
+    }
+
+    /// Returns the smallest integer factor that an image of `size` has to be downscaled by for its RGBA pixels to fit
+    /// in [`PngLoaderBuilder::max_decoded_bytes_per_load`], which is 1 if it already fits.
+    pub fn budget_downscale(&self, size: [usize; 2]) -> usize {
+        let Some(max_bytes) = self.max_decoded_bytes_per_load else {
+            return 1;
+        };
+        let bytes = |factor: usize| {
+            size[0].div_ceil(factor).saturating_mul(size[1].div_ceil(factor)).saturating_mul(size_of::<Color32>())
+        };
+
+        // The square root of the ratio is a lower bound on the factor, which rounding dimensions up can push higher
+        let mut factor = ((bytes(1) as f64 / max_bytes.max(1) as f64).sqrt() as usize).max(1);
+        while bytes(factor) > max_bytes && factor < size[0].max(size[1]) {
+            factor += 1;
+        }
+        factor
+    }
+
+    /// Fails with [`PngLoadError::Corrupt`] if CRCs are verified and one of them doesn't match.
+    pub fn check_crcs(&self, bytes: &[u8]) -> Result<(), PngLoadError> {
+        if !self.verify_crc {
+            return Ok(());
+        }
+        match find_bad_crc(bytes) {
+            Some(chunk) => Err(PngLoadError::Corrupt { chunk }),
+            None => Ok(()),
+        }
+    }
+
+    /// Passes the error of a failed load of `uri` to the error callback, if there is one, and logs it with the `log`
+    /// feature.
+    pub fn report_error<T>(&self, uri: &str, result: &Result<T, Arc<PngLoadError>>) {
+        #[cfg(feature = "log")]
+        if let Err(err) = result {
+            log::warn!("failed to load {uri}: {err}");
+        }
+        if let (Some(on_error), Err(err)) = (&self.on_error, result) {
+            on_error(uri, err);
+        }
+    }
+
+    /// Calls the eviction callback with the URI of every key in `evicted`. The cache must not be locked.
+    pub fn report_evictions(&self, evicted: Vec<Key>) {
+        if let Some(on_evict) = &self.on_evict {
+            for (uri, _) in &evicted {
+                on_evict(uri);
+            }
+        }
+    }
+
+    /// Returns whether decodes should be run through [`Config::spawn`] instead of immediately.
+    pub fn decodes_in_background(&self) -> bool {
+        // Results of background decodes are delivered through the cache, so they need it to be enabled
+        self.background_decoding && self.cache_enabled && (self.spawner.is_some() || !cfg!(target_arch = "wasm32"))
+    }
+
+    /// Called whenever the bytes of an image aren't available yet.
+    pub fn bytes_pending(&self, ctx: &Context) {
+        if self.repaint_while_pending {
+            ctx.request_repaint();
+        }
+    }
+
+    pub fn spawn(&self, job: impl FnOnce() + Send + 'static) {
+        match &self.spawner {
+            Some(spawner) => spawner(Box::new(job)),
+            None => drop(std::thread::spawn(job)),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_dimensions: Some(PngLoader::DEFAULT_MAX_DIMENSIONS),
+            max_cache_bytes: None,
+            max_cache_entries: None,
+            max_entry_bytes: None,
+            max_decoded_bytes_per_load: None,
+            lazy_decoding: None,
+            sniff_content: false,
+            preserve_16_bit: false,
+            dither_16_bit: false,
+            gamma_correction: false,
+            premultiply_alpha: false,
+            extensions: HashSet::default(),
+            mime_predicate: None,
+            strict_mime: false,
+            cache_enabled: true,
+            max_texture_side: None,
+            oversized_textures: OversizedTexture::Reject,
+            broken_images: BrokenImages::Reject,
+            background_decoding: false,
+            spawner: None,
+            max_concurrent_decodes: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            decode_retries: 0,
+            decode_retry_backoff: Duration::ZERO,
+            reuse_buffers: false,
+            flip_vertically: false,
+            flatten_over: None,
+            cache_capacity: 0,
+            on_error: None,
+            on_evict: None,
+            detect_changes: false,
+            compact_palettes: false,
+            repaint_while_pending: false,
+            byte_error_backoff: Duration::ZERO,
+            fallback: None,
+            texture_options: HashMap::default(),
+            nearest_filter_max_side: None,
+            verify_crc: false,
+            downsample_filter: DownsampleFilter::Box,
+            downsample_filters: HashMap::default(),
+            decoder: None,
+        }
+    }
+}
+
+/// Configures a [`PngLoader`] before it is created.
+///
+/// ```no_run
+/// # let ctx = egui::Context::default();
+/// egui_minipng::PngLoader::builder()
+///     .max_dimensions(Some((4096, 4096)))
+///     .max_cache_bytes(Some(64 * 1024 * 1024))
+///     .install(&ctx);
+/// ```
+#[derive(Clone, Default)]
+pub struct PngLoaderBuilder {
+    config: Config,
+}
+
+impl PngLoaderBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rejects images whose width or height exceed `max_dimensions`.
+    ///
+    /// The check happens right after the header is parsed, before any pixel buffer is allocated.
+    /// Defaults to [`PngLoader::DEFAULT_MAX_DIMENSIONS`]; pass `None` to accept images of any size.
+    pub fn max_dimensions(mut self, max_dimensions: Option<(u32, u32)>) -> Self {
+        self.config.max_dimensions = max_dimensions;
+        self
+    }
+
+    /// Evicts the least recently used images once the cache grows beyond `max_cache_bytes`.
+    ///
+    /// Defaults to `None`, which lets the cache grow without bound.
+    pub fn max_cache_bytes(mut self, max_cache_bytes: Option<usize>) -> Self {
+        self.config.max_cache_bytes = max_cache_bytes;
+        self
+    }
+
+    /// Evicts the least recently used images once the cache holds more than `max_cache_entries` of them, counting
+    /// each size of an image separately.
+    ///
+    /// This bounds the cache's bookkeeping when loading many tiny images, whose pixels hardly weigh anything next to
+    /// their overhead. It can be combined with [`Self::max_cache_bytes`], in which case exceeding either limit
+    /// evicts images. Defaults to `None`, which lets the cache grow without bound.
+    pub fn max_cache_entries(mut self, max_cache_entries: Option<usize>) -> Self {
+        self.config.max_cache_entries = max_cache_entries;
+        self
+    }
+
+    /// Never caches images that take up more than `max_entry_bytes` once decoded to RGBA, so that occasional huge
+    /// images don't evict many small ones.
+    ///
+    /// Such images are still loaded, but are decoded again every time they are loaded, always on the calling thread
+    /// since background decodes deliver their results through the cache. Images added with
+    /// [`PngLoader::insert_bytes`] are always cached, as they can't be loaded otherwise. Defaults to `None`, which
+    /// caches images of any size.
+    pub fn max_entry_bytes(mut self, max_entry_bytes: Option<usize>) -> Self {
+        self.config.max_entry_bytes = max_entry_bytes;
+        self
+    }
+
+    /// Downscales images that would take up more than `max_bytes` once decoded to RGBA, by the smallest integer
+    /// factor that makes them fit, instead of decoding them at full size.
+    ///
+    /// Unlike [`Self::max_entry_bytes`], this adapts the quality of large images to fit rather than keeping them out
+    /// of the cache. The budget applies to the size that was asked for, so thumbnails that are small enough already
+    /// aren't downscaled further. [`PngLoader::stats`] tells the factor that each image was downscaled by. Defaults to
+    /// `None`, which decodes images at the size asked for whatever it takes.
+    pub fn max_decoded_bytes_per_load(mut self, max_bytes: Option<usize>) -> Self {
+        self.config.max_decoded_bytes_per_load = max_bytes;
+        self
+    }
+
+    /// Only keeps images decoded while they are in use, trading decoding time for memory in apps with large
+    /// catalogs of mostly idle images.
+    ///
+    /// Decoded images that haven't been loaded in the last `idle_frames` frames are dropped, and decoded again from
+    /// their compressed bytes once they are loaded next. Those bytes are already kept by egui's byte loaders, so only
+    /// images added with [`PngLoader::insert_bytes`] have theirs kept by the loader, and are only decoded once they
+    /// are first loaded. Images that are loaded every frame stay decoded, as do failures and animations. Idle images
+    /// are only dropped while some image is being loaded.
+    ///
+    /// Defaults to `None`, which decodes images right away and keeps them decoded until they are forgotten or
+    /// evicted.
+    pub fn lazy_decoding(mut self, idle_frames: Option<u64>) -> Self {
+        self.config.lazy_decoding = idle_frames;
+        self
+    }
+
+    /// Enables or disables content sniffing.
+    ///
+    /// When enabled, URIs without a `.png` extension are not rejected outright. Instead, their bytes are fetched
+    /// and checked for a PNG signature before the loader decides whether it supports them. This is slower than
+    /// the default extension check, but allows loading PNGs from URIs such as `/avatar` or `asset?id=42`.
+    pub fn sniff_content(mut self, enabled: bool) -> Self {
+        self.config.sniff_content = enabled;
+        self
+    }
+
+    /// Keeps the full precision of 16-bit images, available through [`PngLoader::rgba16`].
+    ///
+    /// egui itself can only display 8-bit images, so the images returned by the loader are unaffected. Each 16-bit
+    /// image additionally keeps an [`Rgba16Image`](crate::Rgba16Image) in memory, which costs 8 more bytes per
+    /// pixel. 8-bit images are decoded exactly as before.
+    pub fn preserve_16_bit(mut self, enabled: bool) -> Self {
+        self.config.preserve_16_bit = enabled;
+        self
+    }
+
+    /// Converts 16-bit images to 8 bits with ordered dithering instead of dropping the low byte of each channel,
+    /// which keeps smooth gradients from banding.
+    ///
+    /// This adds a fine, regular noise pattern to the image. Decoding 16-bit images this way also takes a full
+    /// precision copy of them that is dropped afterwards, needing 8 more bytes per pixel while decoding. 8-bit
+    /// images are unaffected. Disabled by default.
+    pub fn dither_16_bit(mut self, enabled: bool) -> Self {
+        self.config.dither_16_bit = enabled;
+        self
+    }
+
+    /// Converts images with a non-standard `gAMA` chunk to sRGB, so that they blend correctly.
+    ///
+    /// Images with an `sRGB` chunk or without a `gAMA` chunk are assumed to be sRGB already and are left untouched.
+    /// Disabled by default, since it adds a pass over every pixel of affected images.
+    pub fn gamma_correction(mut self, enabled: bool) -> Self {
+        self.config.gamma_correction = enabled;
+        self
+    }
+
+    /// Premultiplies colors by alpha directly on their sRGB values.
+    ///
+    /// egui always stores premultiplied colors, and by default premultiplies images in linear space. Premultiplying
+    /// the encoded values instead matches what most other image pipelines do, and avoids dark fringes around soft
+    /// edges with renderers that filter textures in gamma space. Fully opaque images are unaffected either way.
+    pub fn premultiply_alpha(mut self, enabled: bool) -> Self {
+        self.config.premultiply_alpha = enabled;
+        self
+    }
+
+    /// Also accepts URIs with the given file extension, such as `"icon"` or `"tex"`, on top of `png` and `apng`.
+    ///
+    /// Extensions are matched case-insensitively, and a leading dot is ignored.
+    pub fn extension(mut self, extension: &str) -> Self {
+        self.config.extensions.insert(extension.trim_start_matches('.').to_owned());
+        self
+    }
+
+    /// Also accepts mime types for which `predicate` returns `true`, on top of the standard PNG and APNG mimes.
+    pub fn mime_predicate(mut self, predicate: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        self.config.mime_predicate = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Only decodes bytes whose byte loader reported a PNG mime type, such as `image/png`, leaving everything else
+    /// to other loaders.
+    ///
+    /// By default, bytes without a mime type are decoded as long as their URI looks like a PNG, or their content
+    /// does when [sniffing](Self::sniff_content). This is meant for untrusted remote content, where only what the
+    /// server declared as a PNG should be decoded. Disabled by default.
+    pub fn strict_mime(mut self, enabled: bool) -> Self {
+        self.config.strict_mime = enabled;
+        self
+    }
+
+    /// Enables or disables caching of decoded images.
+    ///
+    /// With caching disabled, every load decodes the image from scratch and nothing is kept in memory by the
+    /// loader, including animations and 16-bit copies, except for the bytes given to
+    /// [`PngLoader::insert_bytes`](crate::PngLoader::insert_bytes), which no byte loader could fetch again. egui
+    /// still caches the textures it creates from the images, so this suits images that are only shown once, such as
+    /// splash screens. Enabled by default.
+    pub fn cache_enabled(mut self, enabled: bool) -> Self {
+        self.config.cache_enabled = enabled;
+        self
+    }
+
+    /// Overrides the maximum texture size, which is otherwise queried from the renderer through
+    /// [`egui::InputState::max_texture_side`].
+    pub fn max_texture_side(mut self, max_texture_side: Option<usize>) -> Self {
+        self.config.max_texture_side = max_texture_side;
+        self
+    }
+
+    /// Chooses whether images larger than the maximum texture size are rejected or downscaled to fit.
+    ///
+    /// Defaults to [`OversizedTexture::Reject`], so that images never lose quality without opting in.
+    pub fn oversized_textures(mut self, oversized_textures: OversizedTexture) -> Self {
+        self.config.oversized_textures = oversized_textures;
+        self
+    }
+
+    /// Chooses whether images with a chunk that minipng can't make sense of are rejected, or decoded without it.
+    ///
+    /// See [`BrokenImages`] for which errors this covers. Defaults to [`BrokenImages::Reject`].
+    pub fn broken_images(mut self, broken_images: BrokenImages) -> Self {
+        self.config.broken_images = broken_images;
+        self
+    }
+
+    /// Decodes images on a background thread instead of blocking the frame that first shows them.
+    ///
+    /// While an image is being decoded, loading it returns [`egui::load::ImagePoll::Pending`], and egui is asked to
+    /// repaint once it is ready. Each image is decoded by a new [`std::thread`] unless a
+    /// [`spawner`](Self::spawner) is provided. This needs the cache to be enabled, and has no effect on `wasm32`
+    /// targets without a spawner, where images are decoded immediately as usual. Disabled by default.
+    pub fn background_decoding(mut self, enabled: bool) -> Self {
+        self.config.background_decoding = enabled;
+        self
+    }
+
+    /// Runs background decodes through `spawner`, such as a thread pool, and enables
+    /// [`background_decoding`](Self::background_decoding).
+    pub fn spawner(mut self, spawner: impl Fn(Box<dyn FnOnce() + Send>) + Send + Sync + 'static) -> Self {
+        self.config.spawner = Some(Arc::new(spawner));
+        self.config.background_decoding = true;
+        self
+    }
+
+    /// Runs at most `max` background decodes at once, so that loading a folder full of large images doesn't decode
+    /// all of them at the same time.
+    ///
+    /// The other images stay [pending](egui::load::ImagePoll::Pending) until their turn comes, without taking up a
+    /// thread in the meantime. This has no effect on images decoded immediately, and a `max` of 0 is treated as 1.
+    /// Defaults to the number of available cores.
+    pub fn max_concurrent_decodes(mut self, max: usize) -> Self {
+        self.config.max_concurrent_decodes = max;
+        self
+    }
+
+    /// Retries background decodes that fail in a way that may not happen again, such as running out of memory, up
+    /// to `retries` times, waiting for `backoff` before each retry.
+    ///
+    /// Only the final failure is cached and reported. See [`PngLoadError::is_transient`] for which failures are
+    /// retried; those that come from the image itself, such as corrupt data, never are. Images decoded immediately
+    /// aren't retried, so as not to block the frame. Defaults to no retries.
+    pub fn decode_retries(mut self, retries: u32, backoff: Duration) -> Self {
+        self.config.decode_retries = retries;
+        self.config.decode_retry_backoff = backoff;
+        self
+    }
+
+    /// Recycles the scratch buffers that images are decoded into, instead of allocating new ones for every image.
+    /// Only opaque grayscale images, 16-bit images and [compacted palettes](Self::compact_palettes) need such buffers;
+    /// other images are decoded straight into the memory of their pixels.
+    ///
+    /// This cuts allocator churn when loading many similarly sized images, at the cost of keeping a few buffers as
+    /// large as the largest recently decoded images in memory. They are included in [`PngLoader`]'s byte size.
+    /// Disabled by default.
+    pub fn reuse_buffers(mut self, enabled: bool) -> Self {
+        self.config.reuse_buffers = enabled;
+        self
+    }
+
+    /// Stores images bottom-up instead of top-down, for custom renderers that expect the origin of textures at the
+    /// bottom-left.
+    ///
+    /// This applies to every image produced by the loader, including animation frames and 16-bit copies. egui's own
+    /// painter expects top-down images, so leave this disabled for images drawn by egui. Disabled by default.
+    pub fn flip_vertically(mut self, enabled: bool) -> Self {
+        self.config.flip_vertically = enabled;
+        self
+    }
+
+    /// Composites transparent images over `background`, so that every image produced by the loader is fully opaque.
+    ///
+    /// This avoids halos around the edges of icons drawn on a panel of a known color. The background itself is
+    /// always treated as opaque, and images without any transparent pixels are left untouched. Defaults to `None`.
+    pub fn flatten_over(mut self, background: Option<Color32>) -> Self {
+        self.config.flatten_over = background;
+        self
+    }
+
+    /// Preallocates room in the cache for `capacity` entries, which avoids rehashing it as it grows when the number
+    /// of images to load is known in advance.
+    pub fn cache_capacity(mut self, capacity: usize) -> Self {
+        self.config.cache_capacity = capacity;
+        self
+    }
+
+    /// Calls `on_error` with the URI and error of every image that fails to load.
+    ///
+    /// Failures are cached, so this is called once for each size an image is requested at and once for its
+    /// animation, rather than on every frame that tries to show it, unless caching is disabled. The callback is never
+    /// called while the loader's cache is locked, so it may use the loader itself. Background decodes call it from
+    /// their worker thread.
+    pub fn on_error(mut self, on_error: impl Fn(&str, &PngLoadError) + Send + Sync + 'static) -> Self {
+        self.config.on_error = Some(Arc::new(on_error));
+        self
+    }
+
+    /// Calls `on_evict` with the URI of every entry that is evicted from the cache to keep it within
+    /// [`Self::max_cache_bytes`] and [`Self::max_cache_entries`], e.g. to keep an app's own asset tracking in sync.
+    ///
+    /// Each size of an image is evicted separately, so the URI may be passed once for each of them. Images that are
+    /// forgotten aren't reported. Like [`Self::on_error`], the callback is never called while the cache is locked.
+    pub fn on_evict(mut self, on_evict: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.config.on_evict = Some(Arc::new(on_evict));
+        self
+    }
+
+    /// Re-decodes images whose bytes have changed since they were cached, e.g. to hot-reload assets.
+    ///
+    /// A fingerprint of the bytes is kept with every cached image and compared against the bytes egui currently has
+    /// for its URI whenever the image is loaded, which costs a hash of the whole file per load. egui's texture loader
+    /// only loads an image when it has no texture for it, so for an [`egui::Image`] the check runs once per texture
+    /// it uploads, and calls on the loader itself, such as [`PngLoader::load_async`](crate::PngLoader::load_async),
+    /// are checked every time. Animations aren't checked. Disabled by default.
+    pub fn detect_changes(mut self, enabled: bool) -> Self {
+        self.config.detect_changes = enabled;
+        self
+    }
+
+    /// Caches indexed images as their palette and one byte per pixel, instead of 4 bytes per pixel.
+    ///
+    /// The pixels are only expanded when the image is handed to egui, which uploads them to a texture and then lets
+    /// go of them, so only the compact copy stays in memory. That expansion is repeated every time egui asks for the
+    /// image again, e.g. at another size or after forgetting its texture. Indexed images that are downscaled are
+    /// cached as usual, and other images are unaffected, so 16-bit ones still keep their
+    /// [16-bit copies](Self::preserve_16_bit). Disabled by default.
+    pub fn compact_palettes(mut self, enabled: bool) -> Self {
+        self.config.compact_palettes = enabled;
+        self
+    }
+
+    /// Requests a repaint whenever the bytes of an image are still being fetched, so that egui polls it again on the
+    /// next frame.
+    ///
+    /// egui's own byte loaders already request a repaint once they are done, so this is only needed for custom
+    /// [`BytesLoader`](egui::load::BytesLoader)s that finish out-of-band without doing so, which would otherwise
+    /// leave images pending until something else repaints. It keeps egui repainting for as long as any image is
+    /// pending. Disabled by default.
+    pub fn repaint_while_pending(mut self, enabled: bool) -> Self {
+        self.config.repaint_while_pending = enabled;
+        self
+    }
+
+    /// Waits for `backoff` before fetching the bytes of an image again after egui's byte loaders failed to fetch
+    /// them, e.g. because a server is down, instead of asking them again on every frame.
+    ///
+    /// Loads in the meantime fail with the same error. Only [`LoadError::Loading`](egui::load::LoadError::Loading)
+    /// errors are held back, so URIs that no byte loader supports are still passed on to other loaders right away.
+    /// Forgetting an image, or calling [`PngLoader::clear_errors`], retries it on its next load. Defaults to zero,
+    /// which retries on every load.
+    pub fn byte_error_backoff(mut self, backoff: Duration) -> Self {
+        self.config.byte_error_backoff = backoff;
+        self
+    }
+
+    /// Shows `fallback` in place of images that fail to load, such as a "broken image" placeholder, so that they
+    /// don't leave a hole in the layout.
+    ///
+    /// Failures are still cached and reported as usual, and can be inspected with [`PngLoader::error`]; only what
+    /// [`ImageLoader::load`](egui::load::ImageLoader::load) returns changes. This includes images whose bytes
+    /// couldn't be fetched, but not URIs that the loader leaves to other loaders. Defaults to `None`, which returns
+    /// the errors instead.
+    pub fn fallback(mut self, fallback: Option<Arc<ColorImage>>) -> Self {
+        self.config.fallback = fallback;
+        self
+    }
+
+    /// Suggests `options` for the image at `uri` from [`PngLoader::texture_options_for`], e.g. nearest filtering
+    /// for a pixel art sprite sheet. This takes precedence over [`Self::nearest_filter_max_side`].
+    pub fn texture_options(mut self, uri: &str, options: TextureOptions) -> Self {
+        self.config.texture_options.insert(normalize_uri(uri).into_owned(), options);
+        self
+    }
+
+    /// Suggests nearest filtering from [`PngLoader::texture_options_for`] for images whose width and height are both
+    /// at most `max_side`, which are likely to be pixel art or icons meant to be drawn crisply.
+    ///
+    /// Defaults to `None`, which suggests linear filtering for every image without its own
+    /// [texture options](Self::texture_options).
+    pub fn nearest_filter_max_side(mut self, max_side: Option<u32>) -> Self {
+        self.config.nearest_filter_max_side = max_side;
+        self
+    }
+
+    /// Verifies the CRC of every chunk before decoding an image, and rejects images with a mismatch as
+    /// [`PngLoadError::Corrupt`] instead of decoding data that may have been silently corrupted.
+    ///
+    /// minipng never checks CRCs, so by default such images are decoded as if nothing was wrong. Verifying them
+    /// costs an extra pass over the bytes of every image. Disabled by default.
+    pub fn verify_crc(mut self, enabled: bool) -> Self {
+        self.config.verify_crc = enabled;
+        self
+    }
+
+    /// Sets the filter used to downsample images to a smaller size. Defaults to [`DownsampleFilter::Box`].
+    pub fn downsample_filter(mut self, filter: DownsampleFilter) -> Self {
+        self.config.downsample_filter = filter;
+        self
+    }
+
+    /// Downsamples the image at `uri` with `filter` instead of the [default one](Self::downsample_filter), e.g.
+    /// [`DownsampleFilter::Nearest`] for line art among photos.
+    pub fn downsample_filter_for(mut self, uri: &str, filter: DownsampleFilter) -> Self {
+        self.config.downsample_filters.insert(normalize_uri(uri).into_owned(), filter);
+        self
+    }
+
+    /// Decodes images with `decoder` instead of the loader's own [`MinipngDecoder`](crate::MinipngDecoder), e.g. to
+    /// cache images in a format other than PNG.
+    ///
+    /// Such images are loaded like any other, at any size and with the same caching, limits and background decoding.
+    /// The PNG-specific options, such as [gamma correction](Self::gamma_correction) and
+    /// [16-bit](Self::preserve_16_bit) and [palette](Self::compact_palettes) handling, are left to the decoder, and
+    /// so are ignored. Formats with their own file extensions or mime types also need [`Self::extension`] or
+    /// [`Self::mime_predicate`]. Images registered with
+    /// [`PngLoader::insert_bytes`], animations and the other methods that read PNG data directly keep using minipng.
+    pub fn decoder(mut self, decoder: Arc<dyn Decoder>) -> Self {
+        self.config.decoder = Some(decoder);
+        self
+    }
+
+    pub fn build(self) -> PngLoader {
+        self.build_with_hasher(Default::default())
+    }
+
+    /// Builds a loader whose caches use `hasher` instead of egui's default hasher, e.g. a deterministic one.
+    ///
+    /// The loader can then be installed with [`Context::add_image_loader`].
+    pub fn build_with_hasher<S: BuildHasher + Clone + Send + Sync + 'static>(self, hasher: S) -> PngLoader<S> {
+        PngLoader::from_config(self.config, hasher)
+    }
+
+    /// Builds the loader and installs it into `context`, returning a handle to it.
+    ///
+    /// Installing is idempotent: if a loader was already installed into `context` this way, no second one is added,
+    /// and the handle to the first one is returned instead, with the settings it was built with. Loaders added to
+    /// egui directly, e.g. after [`Self::build_with_hasher`], aren't known here.
+    pub fn install(self, context: &Context) -> Arc<PngLoader> {
+        // egui only hands out its loaders as trait objects, so the handle is kept alongside them
+        let id = Id::new(PngLoader::ID);
+        if let Some(loader) = context.data(|data| data.get_temp::<Arc<PngLoader>>(id)) {
+            if context.is_loader_installed(PngLoader::ID) {
+                return loader;
+            }
+        }
+
+        let loader = Arc::new(self.build());
+        context.add_image_loader(loader.clone());
+        context.data_mut(|data| data.insert_temp(id, loader.clone()));
+        loader
+    }
+}
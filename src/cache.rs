@@ -0,0 +1,644 @@
+use crate::{decode::IndexedImage, Entry, ImageStats, PngLoadError};
+use egui::{ahash::RandomState, load::SizeHint, mutex::Mutex, Color32, ColorImage};
+use std::{
+    collections::HashMap,
+    hash::{BuildHasher, Hash, Hasher},
+    mem::size_of,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::Waker,
+    time::{Duration, Instant},
+};
+
+pub(crate) type Key = (String, SizeHint);
+
+/// Whether a cached URI was decoded successfully.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryStatus {
+    /// At least one size of the image was decoded successfully.
+    Loaded,
+
+    /// Every cached load of the image failed.
+    Failed,
+}
+
+/// A decoded image as it is kept in the cache.
+#[derive(Clone)]
+pub(crate) enum CachedImage {
+    Rgba(Arc<ColorImage>),
+
+    /// Only expanded to RGBA when handed out, see [`PngLoaderBuilder::compact_palettes`](crate::PngLoaderBuilder).
+    Indexed(Arc<IndexedImage>),
+}
+
+impl CachedImage {
+    pub fn color_image(&self) -> Arc<ColorImage> {
+        match self {
+            Self::Rgba(image) => image.clone(),
+            Self::Indexed(image) => Arc::new(image.to_color_image()),
+        }
+    }
+
+    pub fn size(&self) -> [usize; 2] {
+        match self {
+            Self::Rgba(image) => image.size,
+            Self::Indexed(image) => image.size,
+        }
+    }
+
+    /// Returns whether any pixel of the image isn't fully opaque.
+    pub fn has_transparency(&self) -> bool {
+        match self {
+            Self::Rgba(image) => image.pixels.iter().any(|pixel| pixel.a() < 255),
+            // Palettes are only cut after the highest used index, so unused colors before it are skipped
+            Self::Indexed(image) => {
+                let mut used = [false; 256];
+                image.indices.iter().for_each(|&index| used[usize::from(index)] = true);
+                image.palette.iter().zip(used).any(|(color, used)| used && color.a() < 255)
+            }
+        }
+    }
+
+    /// Hashes the size and pixels of the image, which is the same whether or not its palette was kept compact.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = RandomState::with_seeds(0, 0, 0, 0).build_hasher();
+        match self {
+            Self::Rgba(image) => {
+                image.size.hash(&mut hasher);
+                image.pixels.iter().for_each(|pixel| pixel.hash(&mut hasher));
+            }
+            Self::Indexed(image) => {
+                image.size.hash(&mut hasher);
+                image.indices.iter().for_each(|&index| image.palette[usize::from(index)].hash(&mut hasher));
+            }
+        }
+        hasher.finish()
+    }
+}
+
+impl From<ColorImage> for CachedImage {
+    fn from(image: ColorImage) -> Self {
+        Self::Rgba(Arc::new(image))
+    }
+}
+
+/// A copy of the entries of a loader's image cache, as taken by [`PngLoader::snapshot`](crate::PngLoader::snapshot).
+///
+/// Images are shared with the cache rather than copied, so taking a snapshot is cheap.
+#[derive(Clone, Default)]
+pub struct CacheSnapshot {
+    pub(crate) entries: Vec<(Key, Entry, Option<u64>)>,
+}
+
+impl CacheSnapshot {
+    /// Returns the number of cached entries, counting each size of an image separately.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the normalized URIs that something was cached for, once for each size and in no particular order.
+    pub fn uris(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|((uri, _), _, _)| uri.as_str())
+    }
+}
+
+impl std::fmt::Debug for CacheSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CacheSnapshot").field("entries", &self.entries.len()).finish_non_exhaustive()
+    }
+}
+
+/// The limits that the cache is kept within by evicting its least recently used entries.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct CacheLimits {
+    pub max_bytes: Option<usize>,
+    pub max_entries: Option<usize>,
+}
+
+impl CacheLimits {
+    fn exceeded_by<S>(&self, cache: &Cache<S>) -> bool {
+        self.max_bytes.is_some_and(|max_bytes| cache.bytes > max_bytes)
+            || self.max_entries.is_some_and(|max_entries| cache.slots.len() > max_entries)
+    }
+}
+
+struct Slot {
+    entry: Entry,
+    last_used: u64,
+    fingerprint: Option<u64>,
+
+    /// Whether the entry should be decoded again, while still being shown until its replacement is ready.
+    stale: bool,
+
+    /// Whether the image has any transparent pixels, once it was first asked for.
+    transparent: Option<bool>,
+
+    /// The pass of egui's context that the entry was last used in, see [`Cache::drop_idle`].
+    last_pass: u64,
+
+    /// When the entry was last used, see [`Cache::forget_stale`].
+    last_access: Instant,
+
+    /// How the entry was decoded, for entries decoded from PNG bytes.
+    stats: Option<ImageStats>,
+
+    /// The [content hash](CachedImage::content_hash) of the image, once it was first asked for.
+    content_hash: Option<u64>,
+}
+
+/// A decode started by a [`DecodeGuard`].
+struct InFlight {
+    /// Set once the decode's result is no longer wanted.
+    cancelled: Arc<AtomicBool>,
+    /// The tasks to wake once the decode is done, see [`Cache::wake_on_decode`].
+    wakers: Vec<Waker>,
+}
+
+/// Decoded images keyed by URI and size hint, with least-recently-used eviction.
+pub(crate) struct Cache<S = RandomState> {
+    slots: HashMap<Key, Slot, S>,
+    /// The keys being decoded.
+    decoding: HashMap<Key, InFlight, S>,
+    /// Counts accesses, so that the order entries were last used in never depends on timing and eviction is
+    /// deterministic.
+    clock: u64,
+    bytes: usize,
+    /// The largest `bytes` has been after an insert and its evictions, since the cache was created or last reset.
+    peak_bytes: usize,
+    /// The pass of egui's context that entries are marked as used in, which only moves on while idle entries are
+    /// being dropped.
+    pass: u64,
+}
+
+/// Hashes the bytes that an image is decoded from, so that the cache can tell when they change.
+pub(crate) fn fingerprint(bytes: &[u8]) -> u64 {
+    RandomState::with_seeds(0, 0, 0, 0).hash_one(bytes)
+}
+
+/// Returns the number of bytes used by the pixels of `image`.
+pub(crate) fn image_byte_size(image: &ColorImage) -> usize {
+    image.pixels.len() * size_of::<Color32>()
+}
+
+/// The strong and weak reference counts that an [`Arc`] stores next to its value.
+pub(crate) const ARC_OVERHEAD: usize = 2 * size_of::<usize>();
+
+/// Estimates the number of bytes taken up by an entry of a map with `K` keys and `V` values, keyed by `uri`,
+/// excluding whatever its value points to.
+///
+/// Hash maps store their keys and values inline, next to a control byte per entry. Unused capacity is not counted,
+/// so this is only an approximation of what the map actually allocated.
+pub(crate) fn map_entry_byte_size<K, V>(uri: &str) -> usize {
+    size_of::<(K, V)>() + 1 + uri.len()
+}
+
+/// Returns the approximate number of bytes that `entry` accounts for in the cache, including the [`Arc`] and
+/// [`ColorImage`] or error it is made of.
+pub(crate) fn entry_byte_size(entry: &Entry) -> usize {
+    ARC_OVERHEAD
+        + match entry {
+            Ok(CachedImage::Rgba(image)) => size_of::<ColorImage>() + image_byte_size(image),
+            Ok(CachedImage::Indexed(image)) => {
+                size_of::<IndexedImage>() + image.palette.len() * size_of::<Color32>() + image.indices.len()
+            }
+            Err(_) => size_of::<PngLoadError>(),
+        }
+}
+
+/// Like [`entry_byte_size`], but also counts the entry's key and its slot in the cache.
+pub(crate) fn slot_byte_size(key: &Key, entry: &Entry) -> usize {
+    map_entry_byte_size::<Key, Slot>(&key.0) + entry_byte_size(entry)
+}
+
+impl<S: BuildHasher + Clone> Cache<S> {
+    pub fn with_capacity_and_hasher(capacity: usize, hasher: S) -> Self {
+        Self {
+            slots: HashMap::with_capacity_and_hasher(capacity, hasher.clone()),
+            decoding: HashMap::with_hasher(hasher),
+            clock: 0,
+            bytes: 0,
+            peak_bytes: 0,
+            pass: 0,
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    /// Looks up an entry that isn't stale and marks it as the most recently used.
+    pub fn get(&mut self, key: &Key) -> Option<&Entry> {
+        let now = self.tick();
+        let slot = self.slots.get_mut(key).filter(|slot| !slot.stale)?;
+        slot.last_used = now;
+        slot.last_pass = self.pass;
+        slot.last_access = Instant::now();
+        Some(&slot.entry)
+    }
+
+    /// Returns whether `key` has an entry that isn't stale, without counting as a use of it.
+    pub fn contains(&self, key: &Key) -> bool {
+        self.slots.get(key).is_some_and(|slot| !slot.stale)
+    }
+
+    /// Returns the entry for `key` if it is stale, so it can be shown while its replacement is being decoded.
+    pub fn get_stale(&self, key: &Key) -> Option<&Entry> {
+        self.slots.get(key).filter(|slot| slot.stale).map(|slot| &slot.entry)
+    }
+
+    /// Marks the entries for which `invalidate` returns `true` as stale.
+    pub fn invalidate(&mut self, mut invalidate: impl FnMut(&Key) -> bool) {
+        for (key, slot) in &mut self.slots {
+            slot.stale |= invalidate(key);
+        }
+    }
+
+    /// Like [`Cache::get`], but treats an entry decoded from bytes with a different `fingerprint` as missing, and
+    /// removes it. Entries are always fresh when `fingerprint` is `None`.
+    pub fn get_fresh(&mut self, key: &Key, fingerprint: Option<u64>) -> Option<&Entry> {
+        if fingerprint.is_some() && self.slots.get(key).is_some_and(|slot| slot.fingerprint != fingerprint) {
+            self.remove(key);
+            return None;
+        }
+        self.get(key)
+    }
+
+    /// Inserts an entry decoded from bytes with the given `fingerprint`, then evicts the least recently used entries
+    /// until the cache fits within `limits`.
+    ///
+    /// The inserted entry itself is never evicted, even if it alone exceeds the limits. Returns the keys of the
+    /// evicted entries, to be reported once the cache is unlocked.
+    #[must_use = "evictions should be reported with `Config::report_evictions`"]
+    pub fn insert(&mut self, key: Key, entry: Entry, fingerprint: Option<u64>, limits: CacheLimits) -> Vec<Key> {
+        let last_used = self.tick();
+        self.bytes += slot_byte_size(&key, &entry);
+        if let Some(old) = self.slots.insert(
+            key.clone(),
+            Slot {
+                entry,
+                last_used,
+                fingerprint,
+                stale: false,
+                transparent: None,
+                last_pass: self.pass,
+                last_access: Instant::now(),
+                stats: None,
+                content_hash: None,
+            },
+        ) {
+            self.bytes -= slot_byte_size(&key, &old.entry);
+        }
+
+        let mut evicted = Vec::new();
+        while limits.exceeded_by(self) {
+            let oldest = self
+                .slots
+                .iter()
+                .filter(|(k, _)| **k != key)
+                .min_by_key(|(_, slot)| slot.last_used)
+                .map(|(k, _)| k.clone());
+            let Some(oldest) = oldest else {
+                break;
+            };
+            self.remove(&oldest);
+            evicted.push(oldest);
+        }
+        self.peak_bytes = self.peak_bytes.max(self.bytes);
+        evicted
+    }
+
+    /// Moves the cache on to egui's `pass`, dropping the images that haven't been used in the `frames` passes before
+    /// it. Failures are kept, so that they aren't retried.
+    pub fn drop_idle(&mut self, pass: u64, frames: u64) {
+        if pass == self.pass {
+            return;
+        }
+        self.pass = pass;
+
+        let bytes = &mut self.bytes;
+        self.slots.retain(|key, slot| {
+            let kept = slot.entry.is_err() || slot.last_pass.saturating_add(frames) >= pass;
+            if !kept {
+                *bytes -= slot_byte_size(key, &slot.entry);
+            }
+            kept
+        });
+    }
+
+    /// Drops the images that were last used more than `older_than` before `now`, and returns how many were dropped.
+    /// Failures are kept, so that they aren't retried.
+    pub fn forget_stale(&mut self, now: Instant, older_than: Duration) -> usize {
+        let (bytes, len) = (&mut self.bytes, self.slots.len());
+        self.slots.retain(|key, slot| {
+            let kept = slot.entry.is_err() || now.saturating_duration_since(slot.last_access) <= older_than;
+            if !kept {
+                *bytes -= slot_byte_size(key, &slot.entry);
+            }
+            kept
+        });
+        len - self.slots.len()
+    }
+
+    fn remove(&mut self, key: &Key) {
+        if let Some(slot) = self.slots.remove(key) {
+            self.bytes -= slot_byte_size(key, &slot.entry);
+        }
+    }
+
+    /// Keeps only the entries for which `keep` returns `true`.
+    pub fn retain(&mut self, mut keep: impl FnMut(&Key, &Entry) -> bool) {
+        let bytes = &mut self.bytes;
+        self.slots.retain(|key, slot| {
+            let kept = keep(key, &slot.entry);
+            if !kept {
+                *bytes -= slot_byte_size(key, &slot.entry);
+            }
+            kept
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.bytes = 0;
+        self.cancel_decodes(|_| true);
+    }
+
+    /// Cancels the in-flight decodes of the keys matching `cancel`, so that their results are discarded instead of
+    /// cached, and lets those keys be decoded again right away.
+    pub fn cancel_decodes(&mut self, mut cancel: impl FnMut(&Key) -> bool) {
+        self.decoding.retain(|key, in_flight| {
+            let cancel = cancel(key);
+            if cancel {
+                in_flight.cancelled.store(true, Ordering::Relaxed);
+                in_flight.wakers.drain(..).for_each(Waker::wake);
+            }
+            !cancel
+        });
+    }
+
+    /// Has `waker` woken once the decode of `key` is done, whether it finished or was cancelled. Returns `false`, and
+    /// leaves `waker` alone, if `key` isn't being decoded.
+    #[cfg(all(feature = "async", not(target_arch = "wasm32")))]
+    pub fn wake_on_decode(&mut self, key: &Key, waker: &Waker) -> bool {
+        let Some(in_flight) = self.decoding.get_mut(key) else {
+            return false;
+        };
+        if !in_flight.wakers.iter().any(|woken| woken.will_wake(waker)) {
+            in_flight.wakers.push(waker.clone());
+        }
+        true
+    }
+
+    /// Releases the capacity that the cache's maps retained while they were larger.
+    pub fn shrink_to_fit(&mut self) {
+        self.slots.shrink_to_fit();
+        self.decoding.shrink_to_fit();
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn byte_size(&self) -> usize {
+        self.bytes
+    }
+
+    /// Returns whether the image cached under `uri`, at any size, has any pixels that aren't fully opaque.
+    ///
+    /// The pixels are only scanned the first time this is asked, and the answer is remembered after that.
+    pub fn has_transparency(&mut self, uri: &str) -> Option<bool> {
+        self.slots.iter_mut().find_map(|((key, _), slot)| match slot {
+            Slot { entry: Ok(image), transparent, .. } if key == uri => {
+                Some(*transparent.get_or_insert_with(|| image.has_transparency()))
+            }
+            _ => None,
+        })
+    }
+
+    /// Returns the [content hash](CachedImage::content_hash) of every image cached at its original size along with
+    /// its URI.
+    ///
+    /// Each image is only hashed the first time this is asked, and its hash is remembered after that.
+    pub fn content_hashes(&mut self) -> Vec<(String, u64)> {
+        self.slots
+            .iter_mut()
+            .filter_map(|((uri, size_hint), slot)| match slot {
+                Slot { entry: Ok(image), content_hash, .. } if *size_hint == SizeHint::default() => {
+                    Some((uri.clone(), *content_hash.get_or_insert_with(|| image.content_hash())))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Records how the entry for `key` was decoded, if it is still cached.
+    pub fn set_stats(&mut self, key: &Key, stats: ImageStats) {
+        if let Some(slot) = self.slots.get_mut(key) {
+            slot.stats = Some(stats);
+        }
+    }
+
+    /// Returns how the image cached under `uri` was decoded, preferring its full-size entry over smaller ones.
+    pub fn stats(&self, uri: &str) -> Option<ImageStats> {
+        self.slots
+            .iter()
+            .filter(|((key, _), _)| key == uri)
+            .filter_map(|((_, size_hint), slot)| Some((*size_hint == SizeHint::default(), slot.stats?)))
+            .max_by_key(|(full_size, _)| *full_size)
+            .map(|(_, stats)| stats)
+    }
+
+    /// Returns the size of the image cached under `uri`, preferring its full-size entry over smaller ones.
+    pub fn dimensions(&self, uri: &str) -> Option<[usize; 2]> {
+        self.slots
+            .iter()
+            .filter(|((key, _), _)| key == uri)
+            .filter_map(|((_, size_hint), slot)| Some((*size_hint == SizeHint::default(), slot.entry.as_ref().ok()?)))
+            .max_by_key(|(full_size, _)| *full_size)
+            .map(|(_, image)| image.size())
+    }
+
+    pub fn peak_byte_size(&self) -> usize {
+        self.peak_bytes
+    }
+
+    pub fn reset_peak(&mut self) {
+        self.peak_bytes = self.bytes;
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&Key, &Entry)> {
+        self.slots.iter().map(|(key, slot)| (key, &slot.entry))
+    }
+
+    /// Returns the URIs of the entries from least to most recently used, which is the order they are evicted in.
+    #[cfg(test)]
+    fn access_order(&self) -> Vec<&str> {
+        let mut slots: Vec<_> = self.slots.iter().collect();
+        slots.sort_by_key(|(_, slot)| slot.last_used);
+        slots.into_iter().map(|((uri, _), _)| uri.as_str()).collect()
+    }
+
+    /// Like [`Cache::entries`], along with the fingerprint of the bytes each entry was decoded from.
+    pub fn fingerprinted_entries(&self) -> impl Iterator<Item = (&Key, &Entry, Option<u64>)> {
+        self.slots.iter().map(|(key, slot)| (key, &slot.entry, slot.fingerprint))
+    }
+}
+
+/// Marks a key as being decoded for as long as it is alive.
+///
+/// The marker is cleared on drop, so a decode that errors or panics never leaves its key stuck as in-flight.
+pub(crate) struct DecodeGuard<S: BuildHasher + Clone> {
+    cache: Arc<Mutex<Cache<S>>>,
+    key: Key,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl<S: BuildHasher + Clone> DecodeGuard<S> {
+    /// Marks `key` as being decoded, or returns `None` if another caller is already decoding it or has just
+    /// finished doing so. Stale entries are decoded again.
+    pub fn begin(cache: &Arc<Mutex<Cache<S>>>, key: &Key) -> Option<Self> {
+        let mut guarded = cache.lock();
+        if guarded.slots.get(key).is_some_and(|slot| !slot.stale) || guarded.decoding.contains_key(key) {
+            return None;
+        }
+        let cancelled = Arc::new(AtomicBool::new(false));
+        guarded.decoding.insert(key.clone(), InFlight { cancelled: cancelled.clone(), wakers: Vec::new() });
+        Some(Self { cache: cache.clone(), key: key.clone(), cancelled })
+    }
+
+    /// Returns the flag set once the decode is [cancelled](Cache::cancel_decodes).
+    pub fn cancellation(&self) -> Arc<AtomicBool> {
+        self.cancelled.clone()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Inserts the decode's result into the cache along with its `stats`, unless it was cancelled, in which case it
+    /// is dropped. Returns the keys of the entries evicted to make room for it.
+    #[must_use = "evictions should be reported with `Config::report_evictions`"]
+    pub fn finish(
+        &self,
+        entry: Entry,
+        fingerprint: Option<u64>,
+        stats: Option<ImageStats>,
+        limits: CacheLimits,
+    ) -> Vec<Key> {
+        let mut cache = self.cache.lock();
+        // Checking under the lock makes sure that a decode cancelled by forgetting its key never comes back
+        if self.is_cancelled() {
+            return Vec::new();
+        }
+        let evicted = cache.insert(self.key.clone(), entry, fingerprint, limits);
+        if let Some(stats) = stats {
+            cache.set_stats(&self.key, stats);
+        }
+        evicted
+    }
+}
+
+impl<S: BuildHasher + Clone> Drop for DecodeGuard<S> {
+    fn drop(&mut self) {
+        let mut cache = self.cache.lock();
+        // A cancelled decode's key may already be taken by the decode that replaced it
+        if !cache.decoding.get(&self.key).is_some_and(|in_flight| Arc::ptr_eq(&in_flight.cancelled, &self.cancelled)) {
+            return;
+        }
+        let in_flight = cache.decoding.remove(&self.key);
+        drop(cache);
+        // The result is already cached, so woken tasks find it
+        in_flight.into_iter().flat_map(|in_flight| in_flight.wakers).for_each(Waker::wake);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(uri: &str) -> Key {
+        (uri.to_owned(), SizeHint::default())
+    }
+
+    fn uris(keys: Vec<Key>) -> Vec<String> {
+        keys.into_iter().map(|(uri, _)| uri).collect()
+    }
+
+    fn image() -> Entry {
+        Ok(ColorImage::new([1, 1], Color32::WHITE).into())
+    }
+
+    fn max_entries(max_entries: usize) -> CacheLimits {
+        CacheLimits { max_entries: Some(max_entries), ..Default::default() }
+    }
+
+    #[test]
+    fn lookups_move_entries_to_the_back_of_the_access_order() {
+        let mut cache = Cache::with_capacity_and_hasher(0, RandomState::new());
+        for uri in ["a", "b", "c"] {
+            assert!(cache.insert(key(uri), image(), None, CacheLimits::default()).is_empty());
+        }
+        assert_eq!(cache.access_order(), ["a", "b", "c"]);
+
+        cache.get(&key("a"));
+        assert_eq!(cache.access_order(), ["b", "c", "a"]);
+
+        // Checking for an entry doesn't count as using it
+        assert!(cache.contains(&key("b")));
+        assert_eq!(cache.access_order(), ["b", "c", "a"]);
+    }
+
+    #[test]
+    fn evicts_least_recently_used_entries_first() {
+        let mut cache = Cache::with_capacity_and_hasher(0, RandomState::new());
+        for uri in ["a", "b", "c"] {
+            assert!(cache.insert(key(uri), image(), None, max_entries(3)).is_empty());
+        }
+        cache.get(&key("a"));
+
+        assert_eq!(uris(cache.insert(key("d"), image(), None, max_entries(3))), ["b"]);
+        assert_eq!(uris(cache.insert(key("e"), image(), None, max_entries(2))), ["c", "a"]);
+        assert_eq!(cache.access_order(), ["d", "e"]);
+    }
+
+    #[test]
+    fn never_evicts_the_inserted_entry() {
+        let mut cache = Cache::with_capacity_and_hasher(0, RandomState::new());
+        assert!(cache.insert(key("a"), image(), None, CacheLimits::default()).is_empty());
+
+        let limits = CacheLimits { max_bytes: Some(1), ..Default::default() };
+        assert_eq!(uris(cache.insert(key("b"), image(), None, limits)), ["a"]);
+        assert_eq!(cache.access_order(), ["b"]);
+    }
+
+    #[test]
+    fn forgets_images_unused_for_longer_than_the_threshold() {
+        let mut cache = Cache::with_capacity_and_hasher(0, RandomState::new());
+        let error = Err(Arc::new(PngLoadError::Truncated));
+        let start = Instant::now();
+        let minute = Duration::from_secs(60);
+        for (uri, entry, last_access) in
+            [("old", image(), start), ("failed", error, start), ("used", image(), start + minute)]
+        {
+            assert!(cache.insert(key(uri), entry, None, CacheLimits::default()).is_empty());
+            cache.slots.get_mut(&key(uri)).unwrap().last_access = last_access;
+        }
+
+        assert_eq!(cache.forget_stale(start + minute, minute), 0);
+
+        // Failures are kept, so that they aren't retried
+        assert_eq!(cache.forget_stale(start + minute + Duration::from_secs(1), minute), 1);
+        assert_eq!(cache.access_order(), ["failed", "used"]);
+
+        assert_eq!(cache.forget_stale(start + 3 * minute, minute), 1);
+        assert_eq!(cache.access_order(), ["failed"]);
+        assert_eq!(cache.byte_size(), slot_byte_size(&key("failed"), cache.entries().next().unwrap().1));
+    }
+}
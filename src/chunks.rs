@@ -0,0 +1,124 @@
+use crate::PngLoadError;
+
+/// The 8-byte signature that every PNG file starts with.
+pub const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// Returns whether `bytes` start with the PNG signature, whether or not the rest of them is valid, e.g. to pre-screen
+/// buffers before handing them to the loader.
+///
+/// Buffers shorter than the signature never start with it.
+pub fn is_png_signature(bytes: &[u8]) -> bool {
+    bytes.starts_with(&PNG_SIGNATURE)
+}
+
+/// A single chunk of a PNG file.
+pub(crate) struct Chunk<'a> {
+    pub kind: [u8; 4],
+    pub data: &'a [u8],
+
+    /// The CRC stored after the chunk, which isn't checked while iterating.
+    pub crc: u32,
+}
+
+/// Iterates over the chunks of a PNG file, stopping after `IEND`.
+///
+/// Anything after `IEND`, such as the signatures or padding that some exporters append, is ignored, just like minipng
+/// ignores it when decoding.
+pub(crate) struct Chunks<'a> {
+    bytes: &'a [u8],
+}
+
+/// Returns an iterator over the chunks of `bytes`, which must start with the PNG signature.
+pub(crate) fn chunks(bytes: &[u8]) -> Result<Chunks<'_>, PngLoadError> {
+    match bytes.strip_prefix(&PNG_SIGNATURE) {
+        Some(bytes) => Ok(Chunks { bytes }),
+        None => Err(minipng::Error::NotPng.into()),
+    }
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = Result<Chunk<'a>, PngLoadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+        if self.bytes.len() < 12 {
+            self.bytes = &[];
+            return Some(Err(minipng::Error::UnexpectedEof.into()));
+        }
+
+        let len = u32::from_be_bytes(self.bytes[0..4].try_into().unwrap()) as usize;
+        let kind = self.bytes[4..8].try_into().unwrap();
+        let Some(data) = self.bytes.get(8..8 + len).filter(|_| self.bytes.len() >= 12 + len) else {
+            self.bytes = &[];
+            return Some(Err(minipng::Error::BadChunkSize.into()));
+        };
+
+        let crc = u32::from_be_bytes(self.bytes[8 + len..12 + len].try_into().unwrap());
+        self.bytes = if &kind == b"IEND" { &[] } else { &self.bytes[12 + len..] };
+        Some(Ok(Chunk { kind, data, crc }))
+    }
+}
+
+/// Returns whether `bytes` end before the `IEND` chunk, as happens when a download is cut short.
+///
+/// Data that doesn't start with the PNG signature is never considered truncated.
+pub(crate) fn is_truncated(bytes: &[u8]) -> bool {
+    let Ok(chunks) = chunks(bytes) else {
+        return false;
+    };
+
+    let mut ended = false;
+    for chunk in chunks {
+        match chunk {
+            Ok(chunk) => ended = &chunk.kind == b"IEND",
+            Err(_) => return true,
+        }
+    }
+    !ended
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+};
+
+/// Computes the CRC of a chunk's type and data, as stored after the chunk.
+pub(crate) fn crc(kind: &[u8; 4], data: &[u8]) -> u32 {
+    let crc = kind
+        .iter()
+        .chain(data)
+        .fold(0xffffffff, |crc, &byte| CRC_TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8));
+    crc ^ 0xffffffff
+}
+
+/// Returns the type of the first chunk of `bytes` whose stored CRC doesn't match its contents.
+///
+/// Chunks that can't be read at all are left for the decoder to report.
+pub(crate) fn find_bad_crc(bytes: &[u8]) -> Option<[u8; 4]> {
+    chunks(bytes)
+        .ok()?
+        .map_while(Result::ok)
+        .find(|chunk| crc(&chunk.kind, chunk.data) != chunk.crc)
+        .map(|chunk| chunk.kind)
+}
+
+/// Appends a complete chunk to `out`.
+pub(crate) fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    out.extend_from_slice(&crc(kind, data).to_be_bytes());
+}
@@ -0,0 +1,115 @@
+use crate::chunks::chunks;
+use egui::Color32;
+
+/// Values of the `gAMA` chunk that are treated as plain sRGB, as is customary for browsers.
+const SRGB_GAMMA: u32 = 45455;
+
+/// Builds a lookup table that converts 8-bit samples encoded with `gamma` to sRGB.
+fn gamma_to_srgb_lut(gamma: f32) -> [u8; 256] {
+    let mut lut = [0; 256];
+    for (i, value) in lut.iter_mut().enumerate() {
+        let linear = (i as f32 / 255.0).powf(1.0 / gamma);
+        let srgb = if linear <= 0.0031308 { linear * 12.92 } else { 1.055 * linear.powf(1.0 / 2.4) - 0.055 };
+        *value = (srgb * 255.0).round().clamp(0.0, 255.0) as u8;
+    }
+    lut
+}
+
+/// Returns a lookup table that converts the color samples of the PNG in `bytes` to sRGB, or `None` if they already
+/// are sRGB.
+///
+/// Images with an `sRGB` chunk, or without any `gAMA` chunk, are assumed to be sRGB.
+pub(crate) fn gamma_lut(bytes: &[u8]) -> Option<[u8; 256]> {
+    let mut gamma = None;
+    for chunk in chunks(bytes).ok()? {
+        let chunk = chunk.ok()?;
+        match &chunk.kind {
+            b"sRGB" => return None,
+            b"gAMA" if chunk.data.len() == 4 => gamma = Some(u32::from_be_bytes(chunk.data.try_into().unwrap())),
+            b"IDAT" => break,
+            _ => {}
+        }
+    }
+
+    match gamma? {
+        0 | SRGB_GAMMA => None,
+        gamma => Some(gamma_to_srgb_lut(gamma as f32 / 100000.0)),
+    }
+}
+
+/// Applies `lut` to the color channels of unmultiplied RGBA pixels, leaving alpha untouched.
+pub(crate) fn apply_lut(rgba: &mut [u8], lut: &[u8; 256]) {
+    for pixel in rgba.chunks_exact_mut(4) {
+        for channel in &mut pixel[..3] {
+            *channel = lut[usize::from(*channel)];
+        }
+    }
+}
+
+/// Multiplies the color channels of RGBA pixels by their alpha, as is, without converting them to linear space.
+pub(crate) fn premultiply(rgba: &mut [u8]) {
+    for pixel in rgba.chunks_exact_mut(4) {
+        let alpha = u16::from(pixel[3]);
+        for channel in &mut pixel[..3] {
+            *channel = ((u16::from(*channel) * alpha + 127) / 255) as u8;
+        }
+    }
+}
+
+/// Converts a pixel to a shade of gray with the Rec. 709 luma weights, keeping its alpha.
+///
+/// The weights are applied to premultiplied colors. They add up to one, so grays, including half-transparent ones,
+/// are left as they are.
+pub(crate) fn grayscale(pixel: Color32) -> Color32 {
+    let [r, g, b, a] = pixel.to_array().map(f32::from);
+    let luma = (0.2126 * r + 0.7152 * g + 0.0722 * b).round() as u8;
+    Color32::from_rgba_premultiplied(luma, luma, luma, a as u8)
+}
+
+/// Returns a function that converts the color of a pixel from sRGB to linear space, leaving alpha untouched.
+///
+/// Colors are unmultiplied before the transfer function is applied to them, then premultiplied again in linear space.
+pub(crate) fn linearizer() -> impl Fn(Color32) -> Color32 {
+    let mut lut = [0.0; 256];
+    for (i, value) in lut.iter_mut().enumerate() {
+        let srgb = i as f32 / 255.0;
+        *value = if srgb <= 0.04045 { srgb / 12.92 } else { ((srgb + 0.055) / 1.055).powf(2.4) };
+    }
+
+    move |pixel| {
+        let [r, g, b, a] = pixel.to_srgba_unmultiplied();
+        let alpha = f32::from(a) / 255.0;
+        let [r, g, b] = [r, g, b].map(|channel| (lut[usize::from(channel)] * alpha * 255.0).round() as u8);
+        Color32::from_rgba_premultiplied(r, g, b, a)
+    }
+}
+
+/// Keeps only the alpha of a pixel, turning it white with the same alpha.
+pub(crate) fn alpha_mask(pixel: Color32) -> Color32 {
+    Color32::from_white_alpha(pixel.a())
+}
+
+/// Multiplies every channel of a pixel by those of `tint`, which darkens it towards the tint and combines their
+/// alphas.
+///
+/// Both are premultiplied, which gives the same result as multiplying their unmultiplied colors and alphas
+/// separately.
+pub(crate) fn tint(pixel: Color32, tint: Color32) -> Color32 {
+    let mut channels = pixel.to_array();
+    for (channel, tint) in channels.iter_mut().zip(tint.to_array()) {
+        *channel = ((u16::from(*channel) * u16::from(tint) + 127) / 255) as u8;
+    }
+    let [r, g, b, a] = channels;
+    Color32::from_rgba_premultiplied(r, g, b, a)
+}
+
+/// Composites unmultiplied RGBA pixels over an opaque `background`, leaving them fully opaque.
+pub(crate) fn flatten(rgba: &mut [u8], background: [u8; 3]) {
+    for pixel in rgba.chunks_exact_mut(4) {
+        let alpha = u16::from(pixel[3]);
+        for (channel, background) in pixel[..3].iter_mut().zip(background) {
+            *channel = ((u16::from(*channel) * alpha + u16::from(background) * (255 - alpha) + 127) / 255) as u8;
+        }
+        pixel[3] = 255;
+    }
+}
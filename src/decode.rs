@@ -0,0 +1,796 @@
+use crate::{
+    builder::{BrokenImages, Config, DownsampleFilter},
+    cache::CachedImage,
+    chunks::{chunks, is_truncated, write_chunk, PNG_SIGNATURE},
+    color,
+    pool::BufferPool,
+    PngLoadError,
+};
+use egui::{load::SizeHint, Color32, ColorImage};
+use minipng::{BitDepth, ColorType};
+use std::{
+    io::Read,
+    mem::{size_of, size_of_val},
+    sync::Arc,
+};
+
+/// Parses the header of a PNG image.
+///
+/// minipng rejects headers with a width or height of zero as malformed, which this reports as
+/// [`PngLoadError::EmptyImage`] instead. Interlaced images are reported as [`PngLoadError::Interlaced`].
+pub(crate) fn decode_header(bytes: &[u8]) -> Result<minipng::ImageHeader, PngLoadError> {
+    minipng::decode_png_header(bytes).map_err(|err| {
+        if matches!(err, minipng::Error::UnsupportedInterlace) {
+            return PngLoadError::Interlaced;
+        }
+
+        // The IHDR chunk always comes first, right after the signature and the chunk length
+        let ihdr = bytes
+            .strip_prefix(&PNG_SIGNATURE)
+            .and_then(|rest| rest.get(4..16))
+            .filter(|ihdr| ihdr.starts_with(b"IHDR"));
+        if let Some(ihdr) = ihdr {
+            let width = u32::from_be_bytes(ihdr[4..8].try_into().unwrap());
+            let height = u32::from_be_bytes(ihdr[8..12].try_into().unwrap());
+            if width == 0 || height == 0 {
+                return PngLoadError::EmptyImage { width, height };
+            }
+        }
+        err.into()
+    })
+}
+
+/// Computes the size that an image of `size` should be downscaled to in order to satisfy `size_hint`.
+///
+/// The aspect ratio is always preserved, and images are never upscaled. Scale hints multiply both dimensions, which
+/// are rounded to the nearest pixel but never drop below one, so e.g. a scale of 0.5 halves them.
+pub(crate) fn target_size(size: [usize; 2], size_hint: SizeHint) -> [usize; 2] {
+    let [width, height] = [size[0] as f32, size[1] as f32];
+    let scale = match size_hint {
+        SizeHint::Scale(factor) => factor.into_inner(),
+        SizeHint::Width(w) => w as f32 / width,
+        SizeHint::Height(h) => h as f32 / height,
+        SizeHint::Size(w, h) => (w as f32 / width).min(h as f32 / height),
+    };
+
+    // NaN scales come from zero-sized images.
+    if scale.is_nan() || scale >= 1.0 {
+        return size;
+    }
+
+    [((width * scale).round() as usize).max(1), ((height * scale).round() as usize).max(1)]
+}
+
+/// Returns the size of the `[x, y, width, height]` rectangle once clipped to an image of `size`, or `None` if
+/// nothing is left of it.
+pub(crate) fn crop_size(size: [usize; 2], [x, y, width, height]: [usize; 4]) -> Option<[usize; 2]> {
+    let width = width.min(size[0].saturating_sub(x));
+    let height = height.min(size[1].saturating_sub(y));
+    (width > 0 && height > 0).then_some([width, height])
+}
+
+/// Scales the `[x, y, width, height]` rectangle within an image of size `from` to the same part of an image of size
+/// `to`, rounding outwards so that it keeps at least one pixel.
+pub(crate) fn scale_rect([x, y, width, height]: [usize; 4], from: [usize; 2], to: [usize; 2]) -> [usize; 4] {
+    let scale = |value: usize, axis: usize, round_up: bool| {
+        let scaled = value as u128 * to[axis] as u128;
+        let scaled = if round_up { scaled.div_ceil(from[axis] as u128) } else { scaled / from[axis] as u128 };
+        scaled as usize
+    };
+    let [left, top] = [scale(x, 0, false), scale(y, 1, false)];
+    let [right, bottom] = [scale(x + width, 0, true), scale(y + height, 1, true)];
+    [left, top, (right - left).max(1), (bottom - top).max(1)]
+}
+
+/// Copies the `[x, y, width, height]` rectangle out of `image`, clipped to its bounds.
+///
+/// Returns `None` if nothing is left of the rectangle after clipping.
+pub(crate) fn crop(image: &ColorImage, rect: [usize; 4]) -> Option<ColorImage> {
+    let [width, height] = crop_size(image.size, rect)?;
+    let [x, y, ..] = rect;
+    let image_width = image.size[0];
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in y..y + height {
+        pixels.extend_from_slice(&image.pixels[row * image_width + x..row * image_width + x + width]);
+    }
+    Some(ColorImage { size: [width, height], pixels })
+}
+
+/// Reverses the order of the rows of an image that is `width` pixels wide, so that its origin ends up at the
+/// bottom-left.
+pub(crate) fn flip_rows<T>(pixels: &mut [T], width: usize) {
+    let height = pixels.len().checked_div(width).unwrap_or(0);
+    for y in 0..height / 2 {
+        let (top, bottom) = pixels.split_at_mut((height - 1 - y) * width);
+        top[y * width..(y + 1) * width].swap_with_slice(&mut bottom[..width]);
+    }
+}
+
+/// Downsamples `image` to `target` using `filter`.
+pub(crate) fn downsample(image: &ColorImage, target: [usize; 2], filter: DownsampleFilter) -> ColorImage {
+    match filter {
+        DownsampleFilter::Nearest => downsample_nearest(image, target),
+        DownsampleFilter::Box => downsample_box(image, target),
+    }
+}
+
+fn downsample_nearest(image: &ColorImage, target: [usize; 2]) -> ColorImage {
+    let [src_width, src_height] = image.size;
+    let [dst_width, dst_height] = target;
+    let mut pixels = Vec::with_capacity(dst_width * dst_height);
+
+    for dst_y in 0..dst_height {
+        let y = (2 * dst_y + 1) * src_height / (2 * dst_height);
+        let row = &image.pixels[y * src_width..(y + 1) * src_width];
+        pixels.extend((0..dst_width).map(|dst_x| row[(2 * dst_x + 1) * src_width / (2 * dst_width)]));
+    }
+
+    ColorImage { size: target, pixels }
+}
+
+/// Averaging is done on premultiplied colors so that fully transparent pixels don't bleed into their neighbours.
+fn downsample_box(image: &ColorImage, target: [usize; 2]) -> ColorImage {
+    let [src_width, src_height] = image.size;
+    let [dst_width, dst_height] = target;
+    let mut pixels = Vec::with_capacity(dst_width * dst_height);
+
+    for dst_y in 0..dst_height {
+        let y0 = dst_y * src_height / dst_height;
+        let y1 = ((dst_y + 1) * src_height / dst_height).max(y0 + 1);
+        for dst_x in 0..dst_width {
+            let x0 = dst_x * src_width / dst_width;
+            let x1 = ((dst_x + 1) * src_width / dst_width).max(x0 + 1);
+
+            let mut sum = [0u64; 4];
+            for y in y0..y1 {
+                for pixel in &image.pixels[y * src_width + x0..y * src_width + x1] {
+                    for (sum, channel) in sum.iter_mut().zip(pixel.to_array()) {
+                        *sum += u64::from(channel);
+                    }
+                }
+            }
+
+            let count = ((x1 - x0) * (y1 - y0)) as u64;
+            let [r, g, b, a] = sum.map(|sum| ((sum + count / 2) / count) as u8);
+            pixels.push(Color32::from_rgba_premultiplied(r, g, b, a));
+        }
+    }
+
+    ColorImage { size: target, pixels }
+}
+
+/// Returns the number of bytes needed to decode an image, optionally followed by a conversion to 8-bit RGBA.
+///
+/// Unlike [`minipng::ImageHeader::required_bytes`], this is computed with 64-bit arithmetic so that it can't
+/// overflow, and fails if the result is too large to ever be allocated on this target.
+fn required_bytes(header: &minipng::ImageHeader, rgba8: bool) -> Result<usize, PngLoadError> {
+    let (width, height) = (u64::from(header.width()), u64::from(header.height()));
+    let bits_per_pixel = u64::from(header.bit_depth() as u8) * channels(header.color_type());
+    let decompressed = ((width * bits_per_pixel).div_ceil(8) + 1) * height;
+    let required = if rgba8 { decompressed.max(4 * width * height) } else { decompressed };
+
+    usize::try_from(required).ok().filter(|&required| required <= isize::MAX as usize).ok_or(PngLoadError::TooLarge {
+        width: header.width(),
+        height: header.height(),
+        max_dimensions: None,
+    })
+}
+
+fn channels(color_type: ColorType) -> u64 {
+    match color_type {
+        ColorType::Gray | ColorType::Indexed => 1,
+        ColorType::GrayAlpha => 2,
+        ColorType::Rgb => 3,
+        ColorType::Rgba => 4,
+    }
+}
+
+/// Returns the raw samples of the color that the `tRNS` chunk of a grayscale or RGB image marks as transparent.
+///
+/// minipng only applies `tRNS` chunks to indexed images, so this is left for the loader to do.
+fn transparent_color(header: &minipng::ImageHeader, bytes: &[u8]) -> Option<Vec<u16>> {
+    let channels = match header.color_type() {
+        ColorType::Gray => 1,
+        ColorType::Rgb => 3,
+        _ => return None,
+    };
+    for chunk in chunks(bytes).ok()? {
+        let chunk = chunk.ok()?;
+        match &chunk.kind {
+            b"tRNS" if chunk.data.len() == 2 * channels => {
+                return Some(
+                    chunk.data.chunks_exact(2).map(|sample| u16::from_be_bytes([sample[0], sample[1]])).collect(),
+                )
+            }
+            b"IDAT" => break,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Reads sample `index` of a row of decoded pixels with `bits` bits per sample.
+fn sample(row: &[u8], index: usize, bits: usize) -> u16 {
+    match bits {
+        16 => u16::from_be_bytes([row[2 * index], row[2 * index + 1]]),
+        // Samples are packed most significant bits first
+        _ => u16::from((row[index * bits / 8] >> (8 - bits - index * bits % 8)) & ((1u16 << bits) - 1) as u8),
+    }
+}
+
+/// Returns which pixels of a decoded image have exactly the `transparent` color, before they get converted to
+/// 8-bit RGBA and it can no longer be told apart from similar colors.
+fn transparency_mask(header: &minipng::ImageHeader, pixels: &[u8], transparent: &[u16]) -> Vec<bool> {
+    let bits = header.bit_depth() as usize;
+    let channels = transparent.len();
+    let mut mask = Vec::with_capacity(header.width() as usize * header.height() as usize);
+    for row in pixels.chunks_exact(header.bytes_per_row()) {
+        mask.extend(
+            (0..header.width() as usize).map(|x| {
+                transparent.iter().enumerate().all(|(i, &value)| sample(row, x * channels + i, bits) == value)
+            }),
+        );
+    }
+    mask
+}
+
+/// Fails with [`PngLoadError::SizeMismatch`] unless an image of `size` has exactly `pixels` pixels.
+fn check_pixel_count(size: [usize; 2], pixels: usize) -> Result<(), PngLoadError> {
+    if size[0].checked_mul(size[1]) == Some(pixels) {
+        return Ok(());
+    }
+    Err(PngLoadError::SizeMismatch { width: size[0] as u32, height: size[1] as u32, pixels })
+}
+
+/// Decodes a PNG image into a [`ColorImage`] at its original size.
+///
+/// Most images are decoded into an intermediate 8-bit RGBA buffer before being converted, so decoding temporarily
+/// needs about twice the memory of the final image. 8 and 16-bit grayscale images without a transparent color skip
+/// that buffer and are expanded straight from their single channel, needing only 1 or 2 extra bytes per pixel. Either
+/// way, the cached [`ColorImage`] always uses 4 bytes per pixel.
+fn decode_color_image(
+    header: &minipng::ImageHeader,
+    bytes: &[u8],
+    config: &Config,
+    pool: &BufferPool,
+) -> Result<ColorImage, PngLoadError> {
+    let size = [header.width() as _, header.height() as _];
+    let process = PostProcess::new(bytes, config);
+    if config.dither_16_bit && header.bit_depth() == BitDepth::Sixteen {
+        let mut rgba = dither_to_rgba8(&decode_rgba16(header, bytes, pool)?);
+        return process.rgba(size, &mut rgba);
+    }
+    let transparent = transparent_color(header, bytes);
+    match (header.color_type(), header.bit_depth()) {
+        (ColorType::Gray, bit_depth @ (BitDepth::Eight | BitDepth::Sixteen)) if transparent.is_none() => {
+            let mut buffer = pool.take(required_bytes(header, false)?)?;
+            let image = minipng::decode_png(bytes, &mut buffer)?;
+
+            // 16-bit samples are big-endian, so this keeps only the most significant byte
+            let step = if bit_depth == BitDepth::Sixteen { 2 } else { 1 };
+            let pixels: Vec<_> = image.pixels().iter().step_by(step).map(|&value| process.gray(value)).collect();
+            pool.give(buffer);
+            check_pixel_count(size, pixels.len())?;
+            Ok(ColorImage { size, pixels })
+        }
+        // Decoding straight into the pixels of the image saves allocating a scratch buffer and copying out of it
+        _ => {
+            let len = required_bytes(header, true)?;
+            let mut pixels = alloc_pixels(len.div_ceil(4))?;
+            let buffer = pixel_bytes(&mut pixels);
+            let mut image = minipng::decode_png(bytes, &mut buffer[..len])?;
+            let mask = transparent.map(|transparent| transparency_mask(header, image.pixels(), &transparent));
+            image.convert_to_rgba8bpc()?;
+            check_pixel_count(size, image.pixels().len() / 4)?;
+
+            let rgba = &mut buffer[..4 * size[0] * size[1]];
+            for (pixel, _) in rgba.chunks_exact_mut(4).zip(mask.iter().flatten()).filter(|(_, &masked)| masked) {
+                pixel[3] = 0;
+            }
+            process.rgba_in_place(rgba);
+
+            // Non-RGBA images take up less room while compressed, and the filter bytes of RGBA ones are left over
+            pixels.truncate(size[0] * size[1]);
+            pixels.shrink_to_fit();
+            Ok(ColorImage { size, pixels })
+        }
+    }
+}
+
+/// An indexed image that hasn't been expanded to RGBA, which takes about a quarter of the memory of a
+/// [`ColorImage`].
+pub(crate) struct IndexedImage {
+    /// width, height.
+    pub size: [usize; 2],
+
+    /// The colors of the palette, already processed like the pixels of a [`ColorImage`] would be.
+    pub palette: Vec<Color32>,
+
+    /// One palette index per pixel, row by row.
+    pub indices: Vec<u8>,
+}
+
+impl IndexedImage {
+    pub fn to_color_image(&self) -> ColorImage {
+        let pixels = self.indices.iter().map(|&index| self.palette[usize::from(index)]).collect();
+        ColorImage { size: self.size, pixels }
+    }
+}
+
+/// Decodes an indexed image at its original size, without expanding its pixels.
+fn decode_indexed(
+    header: &minipng::ImageHeader,
+    bytes: &[u8],
+    config: &Config,
+    pool: &BufferPool,
+) -> Result<IndexedImage, PngLoadError> {
+    let size = [header.width() as usize, header.height() as usize];
+    let bits = header.bit_depth() as usize;
+    let mut buffer = pool.take(required_bytes(header, false)?)?;
+    let image = minipng::decode_png(bytes, &mut buffer)?;
+
+    // Indices are packed into bytes most significant bits first, and every row starts on a new byte
+    let mask = ((1u16 << bits) - 1) as u8;
+    let mut indices = Vec::with_capacity(size[0] * size[1]);
+    for row in image.pixels().chunks_exact(image.bytes_per_row()) {
+        indices.extend((0..size[0]).map(|x| {
+            let bit = x * bits;
+            (row[bit / 8] >> (8 - bits - bit % 8)) & mask
+        }));
+    }
+
+    // Only the used part of the palette is kept, processed as a one pixel high image so that it gets the same gamma
+    // and alpha handling as other images
+    let used = indices.iter().max().map_or(0, |&max| usize::from(max) + 1);
+    let mut palette: Vec<u8> = (0..used).flat_map(|index| image.palette(index as u8)).collect();
+    let palette = PostProcess::new(bytes, config).rgba([palette.len() / 4, 1], &mut palette)?.pixels;
+    pool.give(buffer);
+
+    if config.flip_vertically {
+        flip_rows(&mut indices, size[0]);
+    }
+    Ok(IndexedImage { size, palette, indices })
+}
+
+/// Converts 16-bit pixels to 8-bit RGBA bytes with ordered dithering, which avoids the banding that dropping the low
+/// byte of each channel causes in smooth gradients.
+fn dither_to_rgba8(image: &Rgba16Image) -> Vec<u8> {
+    // A 4x4 Bayer matrix, whose thresholds are spread evenly between 0 and 1 in steps of 1/16
+    const BAYER: [[u32; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+    let width = image.size[0].max(1);
+    let mut rgba = Vec::with_capacity(image.pixels.len() * 4);
+    for (i, pixel) in image.pixels.iter().enumerate() {
+        let threshold = BAYER[i / width % 4][i % width % 4];
+        // Adds a threshold of (2 * threshold + 1) / 32 of an 8-bit step before rounding down
+        rgba.extend(
+            pixel.map(|channel| ((u32::from(channel) * 32 + (2 * threshold + 1) * 257) / (257 * 32)).min(255) as u8),
+        );
+    }
+    rgba
+}
+
+/// Color processing applied to decoded 8-bit pixels, resolved from the loader's configuration and the image itself.
+struct PostProcess {
+    gamma: Option<[u8; 256]>,
+    premultiply_alpha: bool,
+    flatten_over: Option<Color32>,
+}
+
+impl PostProcess {
+    fn new(bytes: &[u8], config: &Config) -> Self {
+        Self {
+            gamma: if config.gamma_correction { color::gamma_lut(bytes) } else { None },
+            premultiply_alpha: config.premultiply_alpha,
+            flatten_over: config.flatten_over.map(Color32::to_opaque),
+        }
+    }
+
+    /// Builds an opaque gray pixel.
+    fn gray(&self, value: u8) -> Color32 {
+        Color32::from_gray(self.gamma.map_or(value, |lut| lut[usize::from(value)]))
+    }
+
+    /// Builds an image from unmultiplied RGBA pixels, which may be modified in the process.
+    fn rgba(&self, size: [usize; 2], rgba: &mut [u8]) -> Result<ColorImage, PngLoadError> {
+        check_pixel_count(size, rgba.len() / 4)?;
+        self.rgba_in_place(rgba);
+        Ok(ColorImage::from_rgba_premultiplied(size, rgba))
+    }
+
+    /// Converts unmultiplied RGBA pixels to the bytes of the premultiplied [`Color32`]s they become.
+    fn rgba_in_place(&self, rgba: &mut [u8]) {
+        if let Some(lut) = &self.gamma {
+            color::apply_lut(rgba, lut);
+        }
+
+        // Opaque pixels are the same whether premultiplied or not
+        let opaque = rgba.chunks_exact(4).all(|pixel| pixel[3] == 255);
+        if let Some(background) = self.flatten_over.filter(|_| !opaque) {
+            color::flatten(rgba, [background.r(), background.g(), background.b()]);
+        } else if !self.premultiply_alpha {
+            for pixel in rgba.chunks_exact_mut(4) {
+                let color = Color32::from_rgba_unmultiplied(pixel[0], pixel[1], pixel[2], pixel[3]);
+                pixel.copy_from_slice(&color.to_array());
+            }
+        } else if !opaque {
+            color::premultiply(rgba);
+        }
+    }
+}
+
+/// An image with 16 bits per channel, for when 8-bit [`ColorImage`]s aren't precise enough.
+///
+/// This uses 8 bytes per pixel, twice as much as a [`ColorImage`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rgba16Image {
+    /// width, height.
+    pub size: [usize; 2],
+
+    /// The unmultiplied RGBA pixels, row by row, from top to bottom.
+    pub pixels: Vec<[u16; 4]>,
+}
+
+impl Rgba16Image {
+    /// Converts the image to 8 bits per channel by keeping the most significant byte of each channel.
+    pub fn to_color_image(&self) -> ColorImage {
+        let pixels = self
+            .pixels
+            .iter()
+            .map(|rgba| {
+                let [r, g, b, a] = rgba.map(|channel| (channel >> 8) as u8);
+                Color32::from_rgba_unmultiplied(r, g, b, a)
+            })
+            .collect();
+        ColorImage { size: self.size, pixels }
+    }
+}
+
+/// Decodes a PNG image into an [`Rgba16Image`], keeping the full precision of 16-bit images.
+///
+/// Images with a lower bit depth are decoded as usual and expanded to 16 bits.
+fn decode_rgba16(header: &minipng::ImageHeader, bytes: &[u8], pool: &BufferPool) -> Result<Rgba16Image, PngLoadError> {
+    let size = [header.width() as _, header.height() as _];
+    let transparent = transparent_color(header, bytes);
+    if header.bit_depth() != BitDepth::Sixteen {
+        let mut buffer = pool.take(required_bytes(header, true)?)?;
+        let mut image = minipng::decode_png(bytes, &mut buffer)?;
+        let mask = transparent.map(|transparent| transparency_mask(header, image.pixels(), &transparent));
+        image.convert_to_rgba8bpc()?;
+        let mut pixels: Vec<[u16; 4]> = image
+            .pixels()
+            .chunks_exact(4)
+            .map(|rgba| [rgba[0], rgba[1], rgba[2], rgba[3]].map(|channel| u16::from(channel) * 257))
+            .collect();
+        pool.give(buffer);
+        for (pixel, _) in pixels.iter_mut().zip(mask.iter().flatten()).filter(|(_, &masked)| masked) {
+            pixel[3] = 0;
+        }
+        return Ok(Rgba16Image { size, pixels });
+    }
+
+    let mut buffer = pool.take(required_bytes(header, false)?)?;
+    let image = minipng::decode_png(bytes, &mut buffer)?;
+    let samples: Vec<u16> =
+        image.pixels().chunks_exact(2).map(|sample| u16::from_be_bytes([sample[0], sample[1]])).collect();
+    pool.give(buffer);
+    let alpha = |color: &[u16]| if transparent.as_deref() == Some(color) { 0 } else { u16::MAX };
+    let pixels = match header.color_type() {
+        ColorType::Gray => samples.iter().map(|&v| [v, v, v, alpha(&[v])]).collect(),
+        ColorType::GrayAlpha => samples.chunks_exact(2).map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        ColorType::Rgb => samples.chunks_exact(3).map(|p| [p[0], p[1], p[2], alpha(p)]).collect(),
+        ColorType::Rgba => samples.chunks_exact(4).map(|p| [p[0], p[1], p[2], p[3]]).collect(),
+        // Indexed images can't be 16-bit, and minipng rejects them when parsing the header
+        ColorType::Indexed => unreachable!(),
+    };
+    Ok(Rgba16Image { size, pixels })
+}
+
+/// Decodes PNG bytes into an [`Rgba16Image`] at their original size.
+///
+/// 16-bit images keep their full precision; images with a lower bit depth are expanded to 16 bits.
+pub fn decode_png_to_rgba16(bytes: &[u8]) -> Result<Rgba16Image, PngLoadError> {
+    let header = decode_header(bytes)?;
+    if is_truncated(bytes) {
+        return Err(PngLoadError::Truncated);
+    }
+    decode_rgba16(&header, bytes, &BufferPool::default())
+}
+
+/// Like [`load_image_bytes`], but also returns the full-precision image for 16-bit PNGs.
+pub(crate) fn load_image_bytes_rgba16(
+    header: &minipng::ImageHeader,
+    bytes: &[u8],
+    size_hint: SizeHint,
+    filter: DownsampleFilter,
+    config: &Config,
+    pool: &BufferPool,
+) -> Result<(ColorImage, Option<Rgba16Image>), PngLoadError> {
+    if header.bit_depth() != BitDepth::Sixteen {
+        return Ok((load_image_bytes(header, bytes, size_hint, filter, config, pool)?, None));
+    }
+
+    let mut rgba16 = decode_best_effort(bytes, config, |bytes| decode_rgba16(header, bytes, pool))?;
+    if config.flip_vertically {
+        flip_rows(&mut rgba16.pixels, rgba16.size[0]);
+    }
+    let mut rgba: Vec<u8> = if config.dither_16_bit {
+        dither_to_rgba8(&rgba16)
+    } else {
+        rgba16.pixels.iter().flat_map(|rgba| rgba.map(|channel| (channel >> 8) as u8)).collect()
+    };
+    let image = PostProcess::new(bytes, config).rgba(rgba16.size, &mut rgba)?;
+    let target = target_size(image.size, size_hint);
+    if target == image.size {
+        Ok((image, Some(rgba16)))
+    } else {
+        Ok((downsample(&image, target, filter), Some(rgba16)))
+    }
+}
+
+/// Rebuilds the PNG in `bytes` from the only chunks that minipng needs to decode it, or returns `None` if its chunks
+/// are malformed.
+fn critical_chunks(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut png = PNG_SIGNATURE.to_vec();
+    for chunk in chunks(bytes).ok()? {
+        let chunk = chunk.ok()?;
+        if matches!(&chunk.kind, b"IHDR" | b"PLTE" | b"IDAT" | b"IEND") {
+            write_chunk(&mut png, &chunk.kind, chunk.data);
+        }
+    }
+    Some(png)
+}
+
+/// Runs `decode` on `bytes`, and again on their [critical chunks](critical_chunks) alone if minipng rejected one of
+/// the others and the loader makes a [best effort](BrokenImages::BestEffort) to decode such images.
+fn decode_best_effort<T>(
+    bytes: &[u8],
+    config: &Config,
+    decode: impl Fn(&[u8]) -> Result<T, PngLoadError>,
+) -> Result<T, PngLoadError> {
+    match decode(bytes) {
+        Err(err @ PngLoadError::Decode(minipng::Error::BadTrnsChunk | minipng::Error::UnrecognizedChunk))
+            if config.broken_images == BrokenImages::BestEffort =>
+        {
+            critical_chunks(bytes).map_or(Err(err), |bytes| decode(&bytes))
+        }
+        result => result,
+    }
+}
+
+/// Like [`load_image_bytes`], but keeps indexed images compact when they are kept at their original size.
+pub(crate) fn load_image_bytes_compact(
+    header: &minipng::ImageHeader,
+    bytes: &[u8],
+    size_hint: SizeHint,
+    filter: DownsampleFilter,
+    config: &Config,
+    pool: &BufferPool,
+) -> Result<CachedImage, PngLoadError> {
+    let size = [header.width() as usize, header.height() as usize];
+    if header.color_type() == ColorType::Indexed && target_size(size, size_hint) == size {
+        let image = decode_best_effort(bytes, config, |bytes| decode_indexed(header, bytes, config, pool))?;
+        return Ok(CachedImage::Indexed(Arc::new(image)));
+    }
+    load_image_bytes(header, bytes, size_hint, filter, config, pool).map(CachedImage::from)
+}
+
+pub(crate) fn load_image_bytes(
+    header: &minipng::ImageHeader,
+    bytes: &[u8],
+    size_hint: SizeHint,
+    filter: DownsampleFilter,
+    config: &Config,
+    pool: &BufferPool,
+) -> Result<ColorImage, PngLoadError> {
+    let mut image = decode_best_effort(bytes, config, |bytes| decode_color_image(header, bytes, config, pool))?;
+    if config.flip_vertically {
+        flip_rows(&mut image.pixels, image.size[0]);
+    }
+    let target = target_size(image.size, size_hint);
+    if target == image.size {
+        Ok(image)
+    } else {
+        Ok(downsample(&image, target, filter))
+    }
+}
+
+/// Allocates `len` pixels to decode into, failing gracefully instead of aborting if there isn't enough memory.
+pub(crate) fn alloc_pixels(len: usize) -> Result<Vec<Color32>, PngLoadError> {
+    let mut pixels = Vec::new();
+    pixels.try_reserve_exact(len).map_err(|_| PngLoadError::Allocation { bytes: len * size_of::<Color32>() })?;
+    pixels.resize(len, Color32::TRANSPARENT);
+    Ok(pixels)
+}
+
+/// Views pixels as their bytes, four per pixel in RGBA order.
+fn pixel_bytes(pixels: &mut [Color32]) -> &mut [u8] {
+    // SAFETY: `Color32` is a `#[repr(C)]` wrapper around `[u8; 4]`, so a slice of them is laid out like a slice of
+    // four times as many bytes, and any four bytes make up a valid `Color32`.
+    unsafe { std::slice::from_raw_parts_mut(pixels.as_mut_ptr().cast::<u8>(), size_of_val(pixels)) }
+}
+
+/// Decodes PNG bytes into a [`ColorImage`] at their original size.
+///
+/// This is the same decoding path used by [`PngLoader`](crate::PngLoader), for when the bytes are already at hand
+/// and no [`egui::Context`] is involved.
+///
+/// ```no_run
+/// # let bytes: &[u8] = &[];
+/// let image = egui_minipng::decode_png_to_color_image(bytes)?;
+/// # Ok::<(), egui_minipng::PngLoadError>(())
+/// ```
+pub fn decode_png_to_color_image(bytes: &[u8]) -> Result<ColorImage, PngLoadError> {
+    decode_png_with_config(bytes, &Config::default())
+}
+
+/// Decodes PNG bytes into a [`ColorImage`] at their original size, with the color handling that `config` asks for.
+pub(crate) fn decode_png_with_config(bytes: &[u8], config: &Config) -> Result<ColorImage, PngLoadError> {
+    let header = decode_header(bytes)?;
+    if is_truncated(bytes) {
+        return Err(PngLoadError::Truncated);
+    }
+    decode_color_image(&header, bytes, config, &BufferPool::default())
+}
+
+/// Reads a PNG image from `reader`, such as a socket or an entry of an archive, and decodes it like
+/// [`decode_png_to_color_image`] does.
+///
+/// minipng can't decode incrementally, so the whole stream is read into memory first. Failing to read it fails
+/// with [`PngLoadError::Io`].
+pub fn decode_png_from_reader(mut reader: impl Read) -> Result<ColorImage, PngLoadError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    decode_png_to_color_image(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_pngs::*;
+
+    /// Decodes `bytes` the way the loader does with `config`.
+    fn load(bytes: &[u8], size_hint: SizeHint, config: &Config) -> Result<ColorImage, PngLoadError> {
+        let header = decode_header(bytes)?;
+        load_image_bytes(&header, bytes, size_hint, config.downsample_filter, config, &BufferPool::default())
+    }
+
+    #[test]
+    fn broken_chunks_are_dropped_when_making_a_best_effort() {
+        let config = Config::default();
+        assert!(matches!(
+            load(RGBA_2X2_UNKNOWN_CHUNK, SizeHint::default(), &config),
+            Err(PngLoadError::Decode(minipng::Error::UnrecognizedChunk))
+        ));
+
+        let config = Config { broken_images: BrokenImages::BestEffort, ..Config::default() };
+        assert_eq!(
+            load(RGBA_2X2_UNKNOWN_CHUNK, SizeHint::default(), &config).unwrap().pixels,
+            load(RGBA_2X2, SizeHint::default(), &config).unwrap().pixels
+        );
+    }
+
+    #[test]
+    fn failed_allocations_are_reported_instead_of_aborting() {
+        let len = usize::MAX / size_of::<Color32>();
+        assert!(matches!(alloc_pixels(len), Err(PngLoadError::Allocation { bytes }) if bytes == len * 4));
+    }
+
+    /// Pads [`LARGE_HEADER`] to the size that minipng expects the compressed data of such a large image to have at
+    /// least, so that it gets past its header checks.
+    fn large_png() -> Vec<u8> {
+        let mut bytes = LARGE_HEADER.to_vec();
+        bytes.resize(9 << 20, 0);
+        bytes
+    }
+
+    // Its pixels take up 2^34 bytes, more than a 32-bit `usize` holds, so 32-bit targets already reject the header
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn buffer_sizes_are_computed_without_overflowing() {
+        let header = decode_header(&large_png()).unwrap();
+        let decompressed = (4 * 65536 + 1) * 65536;
+        assert_eq!(required_bytes(&header, false).unwrap(), decompressed);
+        assert_eq!(required_bytes(&header, true).unwrap(), decompressed);
+    }
+
+    #[test]
+    fn images_larger_than_the_maximum_dimensions_are_rejected() {
+        let config = Config::default();
+        let result =
+            decode_header(&large_png()).and_then(|header| config.check_dimensions(header.width(), header.height()));
+        assert!(matches!(
+            result,
+            Err(PngLoadError::TooLarge { .. }) | Err(PngLoadError::Decode(minipng::Error::TooLargeForUsize))
+        ));
+    }
+
+    #[test]
+    fn flipping_vertically_reverses_the_rows() {
+        let half_white = Color32::from_rgba_unmultiplied(255, 255, 255, 128);
+        let image = decode_png_to_color_image(RGBA_2X2).unwrap();
+        assert_eq!(image.pixels, [Color32::RED, Color32::GREEN, Color32::BLUE, half_white]);
+
+        let config = Config { flip_vertically: true, ..Config::default() };
+        let flipped = load(RGBA_2X2, SizeHint::default(), &config).unwrap();
+        assert_eq!(flipped.size, [2, 2]);
+        assert_eq!(flipped.pixels, [Color32::BLUE, half_white, Color32::RED, Color32::GREEN]);
+    }
+
+    #[test]
+    fn zero_sized_images_are_reported_as_empty() {
+        assert!(matches!(decode_header(EMPTY_0X1), Err(PngLoadError::EmptyImage { width: 0, height: 1 })));
+        assert!(matches!(decode_png_to_color_image(EMPTY_0X1), Err(PngLoadError::EmptyImage { .. })));
+    }
+
+    // minipng can't deinterlace, so the loader reports these instead of decoding their passes out of order
+    #[test]
+    fn interlaced_images_are_reported() {
+        assert!(matches!(decode_header(RGBA_2X2_INTERLACED), Err(PngLoadError::Interlaced)));
+        assert!(matches!(decode_png_to_color_image(RGBA_2X2_INTERLACED), Err(PngLoadError::Interlaced)));
+        assert!(decode_png_to_color_image(RGBA_2X2).is_ok());
+    }
+
+    #[test]
+    fn truncated_data_is_told_apart_from_corrupt_data() {
+        assert!(matches!(decode_png_to_color_image(RGBA_2X2_TRUNCATED), Err(PngLoadError::Truncated)));
+
+        let corrupt = [&PNG_SIGNATURE[..], b"this is not a chunk"].concat();
+        assert!(matches!(decode_png_to_color_image(&corrupt), Err(PngLoadError::Decode(_))));
+    }
+
+    #[test]
+    fn trns_colors_of_rgb_images_become_transparent() {
+        let image = decode_png_to_color_image(RGB_TRNS_2X1).unwrap();
+        assert_eq!(image.pixels, [Color32::TRANSPARENT, Color32::from_rgb(40, 50, 60)]);
+        let rgba16 = decode_png_to_rgba16(RGB_TRNS_2X1).unwrap();
+        assert_eq!(rgba16.pixels, [[10 * 257, 20 * 257, 30 * 257, 0], [40 * 257, 50 * 257, 60 * 257, u16::MAX]]);
+    }
+
+    #[test]
+    fn downsample_filters_pick_or_average_pixels() {
+        let downsample = |downsample_filter| {
+            let config = Config { downsample_filter, ..Config::default() };
+            load(GRAY_4X1, SizeHint::Width(2), &config).unwrap().pixels
+        };
+        assert_eq!(downsample(DownsampleFilter::Nearest), [Color32::from_gray(64), Color32::from_gray(255)]);
+        assert_eq!(downsample(DownsampleFilter::Box), [Color32::from_gray(32), Color32::from_gray(192)]);
+    }
+
+    #[test]
+    fn scale_hints_downscale_without_upscaling() {
+        let scale = |factor: f32| SizeHint::Scale(factor.into());
+        assert_eq!(target_size([4, 4], scale(0.5)), [2, 2]);
+        assert_eq!(target_size([5, 3], scale(0.5)), [3, 2]);
+        assert_eq!(target_size([5, 3], scale(0.01)), [1, 1]);
+        assert_eq!(target_size([5, 3], scale(1.0)), [5, 3]);
+        assert_eq!(target_size([5, 3], scale(2.0)), [5, 3]);
+
+        let image = load(RGBA_4X4, scale(0.5), &Config::default()).unwrap();
+        assert_eq!(image.size, [2, 2]);
+        assert_eq!(image.pixels, [Color32::RED; 4]);
+    }
+
+    #[test]
+    fn data_after_iend_is_ignored() {
+        let with_trailer = [RGBA_2X2, b"signature appended by an exporter"].concat();
+        assert_eq!(decode_png_to_color_image(&with_trailer).unwrap(), decode_png_to_color_image(RGBA_2X2).unwrap());
+        assert_eq!(decode_png_to_rgba16(&with_trailer).unwrap(), decode_png_to_rgba16(RGBA_2X2).unwrap());
+    }
+
+    #[test]
+    fn pixel_count_mismatches_are_reported_instead_of_panicking() {
+        assert!(check_pixel_count([2, 2], 4).is_ok());
+        assert!(matches!(
+            check_pixel_count([2, 2], 3),
+            Err(PngLoadError::SizeMismatch { width: 2, height: 2, pixels: 3 })
+        ));
+        assert!(check_pixel_count([usize::MAX, 2], 0).is_err());
+
+        // Building a `ColorImage` from these would panic
+        let mut rgba = [255; 12];
+        let result = PostProcess::new(RGBA_2X2, &Config::default()).rgba([2, 2], &mut rgba);
+        assert!(matches!(result, Err(PngLoadError::SizeMismatch { pixels: 3, .. })));
+    }
+}
@@ -0,0 +1,89 @@
+use crate::{
+    builder::Config,
+    chunks::{is_png_signature, is_truncated},
+    decode::{decode_header, load_image_bytes},
+    pool::BufferPool,
+    PngLoadError,
+};
+use egui::{load::SizeHint, ColorImage};
+use std::{fmt, sync::Arc};
+
+/// The size of an image, read by a [`Decoder`] before any of its pixels are decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodedHeader {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Decodes the images that a [`PngLoader`](crate::PngLoader) loads, so that its caching can be used for formats other
+/// than PNG.
+///
+/// Loaders decode with a [`MinipngDecoder`] unless they are built with
+/// [`PngLoaderBuilder::decoder`](crate::PngLoaderBuilder::decoder). Decoders are called from whichever threads decode
+/// images, including the threads of [background decoding](crate::PngLoaderBuilder::background_decoding).
+pub trait Decoder: Send + Sync {
+    /// Reads the size of the image in `bytes` without decoding its pixels.
+    ///
+    /// Failing with [`PngLoadError::Truncated`] tells the loader that the bytes may still be completed by a later
+    /// download, so the failure isn't cached.
+    fn header(&self, bytes: &[u8]) -> Result<DecodedHeader, PngLoadError>;
+
+    /// Decodes the image in `bytes` at its original size.
+    fn decode(&self, bytes: &[u8]) -> Result<ColorImage, PngLoadError>;
+
+    /// Returns whether `bytes` are in this decoder's format at all, so that the loader reports why
+    /// [`Self::header`] failed on them instead of letting other loaders try them.
+    ///
+    /// Defaults to whether [`Self::header`] succeeds.
+    fn recognizes(&self, bytes: &[u8]) -> bool {
+        self.header(bytes).is_ok()
+    }
+}
+
+/// The [`Decoder`] for PNG images, which decodes them with minipng.
+///
+/// A default one decodes like [`decode_png_to_color_image`](crate::decode_png_to_color_image) does. The one a loader
+/// uses when it isn't given another decoder also follows the loader's settings, such as
+/// [gamma correction](crate::PngLoaderBuilder::gamma_correction).
+#[derive(Clone, Default)]
+pub struct MinipngDecoder {
+    config: Arc<Config>,
+    pool: Arc<BufferPool>,
+}
+
+impl MinipngDecoder {
+    pub(crate) fn new(config: Arc<Config>, pool: Arc<BufferPool>) -> Self {
+        Self { config, pool }
+    }
+}
+
+impl fmt::Debug for MinipngDecoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MinipngDecoder").finish_non_exhaustive()
+    }
+}
+
+impl Decoder for MinipngDecoder {
+    /// Reads the header of the PNG image in `bytes`, failing with [`PngLoadError::Truncated`] if the data ends early.
+    fn header(&self, bytes: &[u8]) -> Result<DecodedHeader, PngLoadError> {
+        let header = decode_header(bytes);
+        if is_truncated(bytes) {
+            return Err(PngLoadError::Truncated);
+        }
+        let header = header?;
+        Ok(DecodedHeader { width: header.width(), height: header.height() })
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<ColorImage, PngLoadError> {
+        let header = decode_header(bytes)?;
+        if is_truncated(bytes) {
+            return Err(PngLoadError::Truncated);
+        }
+        let filter = self.config.downsample_filter;
+        load_image_bytes(&header, bytes, SizeHint::default(), filter, &self.config, &self.pool)
+    }
+
+    fn recognizes(&self, bytes: &[u8]) -> bool {
+        is_png_signature(bytes)
+    }
+}
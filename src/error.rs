@@ -0,0 +1,200 @@
+use std::fmt;
+
+/// An error that occurred while loading a PNG image.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PngLoadError {
+    /// The image data could not be decoded, for the reason given by minipng.
+    ///
+    /// The reason can be matched on, e.g. `PngLoadError::Decode(MinipngError::BadIhdr)`, to tell apart corrupt
+    /// headers from corrupt pixel data. Failed loads keep this error, which
+    /// [`PngLoader::error`](crate::PngLoader::error) returns; only egui gets its message.
+    Decode(minipng::Error),
+
+    /// The image is larger than the loader's configured maximum dimensions, or, if `max_dimensions` is `None`,
+    /// too large to fit in memory at all.
+    ///
+    /// Animations whose frames take up more pixels together than a single image of the maximum dimensions, or more
+    /// bytes than the loader's cache limits allow, also fail with this, with `max_dimensions` set to `None`.
+    TooLarge { width: u32, height: u32, max_dimensions: Option<(u32, u32)> },
+
+    /// The image is larger than the largest texture the renderer supports.
+    TooLargeForTexture { width: u32, height: u32, max_side: usize },
+
+    /// The pixel buffer for the image could not be allocated.
+    Allocation { bytes: usize },
+
+    /// The animation chunks of an APNG image are malformed.
+    InvalidAnimation(&'static str),
+
+    /// The image header reports a width or height of zero, leaving nothing to display.
+    EmptyImage { width: u32, height: u32 },
+
+    /// The image is Adam7-interlaced, which minipng doesn't support.
+    Interlaced,
+
+    /// The CRC stored after one of the image's chunks doesn't match its contents, as checked by loaders built with
+    /// [`PngLoaderBuilder::verify_crc`](crate::PngLoaderBuilder::verify_crc).
+    Corrupt { chunk: [u8; 4] },
+
+    /// A region passed to [`PngLoader::load_atlas`](crate::PngLoader::load_atlas) doesn't lie entirely within the
+    /// atlas image, or the rectangle of a `#rect=x,y,w,h` fragment, given as the name, lies entirely outside of the
+    /// image.
+    RegionOutOfBounds { name: String, width: u32, height: u32 },
+
+    /// The image file couldn't be read, by [`PngLoader::load_path`](crate::PngLoader::load_path), or the stream
+    /// passed to [`PngLoader::load_reader`](crate::PngLoader::load_reader) or
+    /// [`decode_png_from_reader`](crate::decode_png_from_reader) couldn't be read.
+    Io(std::io::Error),
+
+    /// minipng decoded a different number of pixels than the image header declares.
+    ///
+    /// This never happens with well-formed images, but keeps malformed ones from panicking instead of failing.
+    SizeMismatch { width: u32, height: u32, pixels: usize },
+
+    /// The image data ends early, as if its download was cut short.
+    ///
+    /// Unlike other errors, this is never cached by the loader, so the image loads once complete data is fetched.
+    Truncated,
+
+    /// A custom [`Decoder`](crate::Decoder) failed to decode the image, for the reason it gives.
+    Backend(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl fmt::Display for PngLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decode(err) => write!(f, "failed to decode png: {err}"),
+            Self::TooLarge { width, height, max_dimensions: Some((max_width, max_height)) } => {
+                write!(f, "image dimensions {width}x{height} exceed the maximum of {max_width}x{max_height}")
+            }
+            Self::TooLarge { width, height, max_dimensions: None } => {
+                write!(f, "image dimensions {width}x{height} are too large to fit in memory")
+            }
+            Self::TooLargeForTexture { width, height, max_side } => {
+                write!(f, "image dimensions {width}x{height} exceed the maximum texture size of {max_side}x{max_side}")
+            }
+            Self::Allocation { bytes } => write!(f, "failed to allocate {bytes} bytes for the image"),
+            Self::InvalidAnimation(reason) => write!(f, "invalid apng animation: {reason}"),
+            Self::EmptyImage { width, height } => {
+                write!(f, "image dimensions {width}x{height} leave nothing to display")
+            }
+            Self::Interlaced => write!(f, "interlaced png images are not supported"),
+            Self::SizeMismatch { width, height, pixels } => {
+                write!(f, "decoded {pixels} pixels, which don't match the image dimensions {width}x{height}")
+            }
+            Self::Truncated => write!(f, "png data ends unexpectedly, it may have been truncated"),
+            Self::Corrupt { chunk } => {
+                write!(f, "png chunk {} is corrupt, its crc doesn't match", chunk.escape_ascii())
+            }
+            Self::RegionOutOfBounds { name, width, height } => {
+                write!(f, "image region {name:?} lies outside of the {width}x{height} image")
+            }
+            Self::Io(err) => write!(f, "failed to read png data: {err}"),
+            Self::Backend(err) => write!(f, "failed to decode image: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for PngLoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Backend(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl PngLoadError {
+    /// Returns the underlying minipng error, if this is a [`PngLoadError::Decode`].
+    pub fn decode_error(&self) -> Option<&minipng::Error> {
+        match self {
+            Self::Decode(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the failure may not happen again if the image is decoded again later, as with failed
+    /// allocations and truncated data, rather than coming from the image itself.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Allocation { .. } | Self::Truncated)
+    }
+}
+
+/// Copies minipng errors, which aren't [`Clone`], for the variants known when this was written.
+#[cfg(all(feature = "async", not(target_arch = "wasm32")))]
+macro_rules! copy_minipng_error {
+    ($err:expr, $($variant:ident),* $(,)?) => {
+        match $err {
+            $(minipng::Error::$variant => Some(minipng::Error::$variant),)*
+            _ => None,
+        }
+    };
+}
+
+#[cfg(all(feature = "async", not(target_arch = "wasm32")))]
+impl PngLoadError {
+    /// Makes a copy of the error, e.g. one shared by the cache. Errors that can't be copied, such as I/O errors, are
+    /// rebuilt from their kind and message.
+    pub(crate) fn duplicate(&self) -> Self {
+        match self {
+            Self::Decode(err) => {
+                let copy = copy_minipng_error!(
+                    err,
+                    UnexpectedEof,
+                    BufferTooSmall,
+                    TooLargeForUsize,
+                    NotPng,
+                    BadIhdr,
+                    UnrecognizedChunk,
+                    BadBlockType,
+                    BadNlen,
+                    TooMuchData,
+                    UnexpectedEob,
+                    BadZlibHeader,
+                    BadCode,
+                    BadHuffmanDict,
+                    BadBackReference,
+                    UnsupportedInterlace,
+                    BadFilter,
+                    BadPlteChunk,
+                    BadTrnsChunk,
+                    NoIdat,
+                    BadAdlerChecksum,
+                    BadChunkSize,
+                    CompressedSizeTooSmall,
+                );
+                copy.map_or_else(|| Self::Backend(err.to_string().into()), Self::Decode)
+            }
+            &Self::TooLarge { width, height, max_dimensions } => Self::TooLarge { width, height, max_dimensions },
+            &Self::TooLargeForTexture { width, height, max_side } => {
+                Self::TooLargeForTexture { width, height, max_side }
+            }
+            &Self::Allocation { bytes } => Self::Allocation { bytes },
+            &Self::InvalidAnimation(reason) => Self::InvalidAnimation(reason),
+            &Self::EmptyImage { width, height } => Self::EmptyImage { width, height },
+            Self::Interlaced => Self::Interlaced,
+            &Self::Corrupt { chunk } => Self::Corrupt { chunk },
+            Self::RegionOutOfBounds { name, width, height } => {
+                Self::RegionOutOfBounds { name: name.clone(), width: *width, height: *height }
+            }
+            Self::Io(err) => Self::Io(std::io::Error::new(err.kind(), err.to_string())),
+            &Self::SizeMismatch { width, height, pixels } => Self::SizeMismatch { width, height, pixels },
+            Self::Truncated => Self::Truncated,
+            Self::Backend(err) => Self::Backend(err.to_string().into()),
+        }
+    }
+}
+
+impl From<std::io::Error> for PngLoadError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<minipng::Error> for PngLoadError {
+    fn from(err: minipng::Error) -> Self {
+        Self::Decode(err)
+    }
+}
@@ -0,0 +1,102 @@
+use crate::{uri::normalize_uri, PngLoadError, PngLoader};
+use egui::{
+    ahash::RandomState,
+    load::{ImagePoll, LoadError, SizeHint},
+    ColorImage, Context,
+};
+use std::{
+    future::Future,
+    hash::BuildHasher,
+    io, mem,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{self, Poll, Waker},
+    thread,
+    time::Duration,
+};
+
+/// How long a load whose bytes are still being fetched waits before checking on its image again.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The tasks waiting on bytes, and whether the timer thread that wakes them is running.
+static BYTE_WAITERS: Mutex<(Vec<Waker>, bool)> = Mutex::new((Vec::new(), false));
+
+/// Wakes `waker` after [`POLL_INTERVAL`], from a timer thread shared by every pending load that runs for as long as
+/// any of them are waiting.
+fn wake_later(waker: &Waker) {
+    let mut waiters = BYTE_WAITERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if !waiters.0.iter().any(|waiting| waiting.will_wake(waker)) {
+        waiters.0.push(waker.clone());
+    }
+    if mem::replace(&mut waiters.1, true) {
+        return;
+    }
+    thread::spawn(|| loop {
+        thread::sleep(POLL_INTERVAL);
+        let mut waiters = BYTE_WAITERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if waiters.0.is_empty() {
+            waiters.1 = false;
+            return;
+        }
+        let wakers = mem::take(&mut waiters.0);
+        drop(waiters);
+        wakers.into_iter().for_each(Waker::wake);
+    });
+}
+
+/// A future that resolves once the image it loads is ready, returned by [`PngLoader::load_async`].
+///
+/// Images being decoded in the background wake the future as soon as they are cached. Nothing tells the loader when
+/// egui's byte loaders are done, though, so while the bytes are still being fetched, the future is checked on again
+/// every few milliseconds from a single timer thread shared by all pending loads. This works with any async runtime.
+#[must_use = "futures do nothing unless polled"]
+pub struct LoadFuture<S = RandomState> {
+    loader: Arc<PngLoader<S>>,
+    ctx: Context,
+    uri: String,
+}
+
+impl<S: BuildHasher + Clone + Send + Sync + 'static> PngLoader<S> {
+    /// Loads the image at `uri` at its original size, like [`egui::load::ImageLoader::load`] would, and resolves
+    /// once it is ready instead of returning [`ImagePoll::Pending`].
+    ///
+    /// The image is cached and shared with synchronous loads as usual, and decoding failures resolve to the same
+    /// errors that [`Self::error`] returns. Bytes that can't be fetched fail with [`PngLoadError::Io`]. Unless
+    /// [background decoding](crate::PngLoaderBuilder::background_decoding) is enabled, the image is decoded on
+    /// whichever thread polls the future, so async runtimes are better off with it enabled.
+    pub fn load_async(self: &Arc<Self>, ctx: &Context, uri: &str) -> LoadFuture<S> {
+        LoadFuture { loader: self.clone(), ctx: ctx.clone(), uri: uri.to_owned() }
+    }
+}
+
+impl<S: BuildHasher + Clone + Send + Sync + 'static> LoadFuture<S> {
+    /// Turns the failure of a load into the error that caused it.
+    fn png_error(&self, err: LoadError) -> PngLoadError {
+        if let Some(err) = self.loader.error(&self.uri) {
+            return err.duplicate();
+        }
+        match err {
+            LoadError::NotSupported => io::Error::new(io::ErrorKind::Unsupported, "the image is not supported").into(),
+            LoadError::Loading(message) if message == PngLoadError::Truncated.to_string() => PngLoadError::Truncated,
+            err => io::Error::other(err.to_string()).into(),
+        }
+    }
+}
+
+impl<S: BuildHasher + Clone + Send + Sync + 'static> Future for LoadFuture<S> {
+    type Output = Result<Arc<ColorImage>, PngLoadError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        match self.loader.load_image(&self.ctx, &self.uri, SizeHint::default()) {
+            Ok(ImagePoll::Ready { image }) => Poll::Ready(Ok(image)),
+            Ok(ImagePoll::Pending { .. }) => {
+                let key = (normalize_uri(&self.uri).into_owned(), SizeHint::default());
+                if !self.loader.cache.lock().wake_on_decode(&key, cx.waker()) {
+                    wake_later(cx.waker());
+                }
+                Poll::Pending
+            }
+            Err(err) => Poll::Ready(Err(self.png_error(err))),
+        }
+    }
+}
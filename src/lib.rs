@@ -1,105 +1,2146 @@
+use apng::decode_animated;
+use builder::Config;
+use cache::{
+    entry_byte_size, fingerprint, image_byte_size, map_entry_byte_size, slot_byte_size, Cache, CachedImage,
+    DecodeGuard, Key, ARC_OVERHEAD,
+};
+use chunks::is_truncated;
+use color::{alpha_mask, grayscale, linearizer};
+use decode::{
+    crop, crop_size, decode_header, downsample, load_image_bytes, load_image_bytes_compact, load_image_bytes_rgba16,
+    scale_rect, target_size,
+};
 use egui::{
-    ahash::HashMap,
-    load::{Bytes, BytesPoll, ImageLoadResult, ImageLoader, ImagePoll, LoadError, SizeHint},
+    ahash::{HashMap, HashSet, RandomState},
+    load::{BytesPoll, ImageLoadResult, ImageLoader, ImagePoll, LoadError, SizeHint},
     mutex::Mutex,
-    ColorImage, Context,
+    Color32, ColorImage, Context, TextureOptions,
+};
+use metrics::Metrics;
+use pool::BufferPool;
+use queue::DecodeQueue;
+use std::{
+    collections::HashMap as StdHashMap,
+    fmt,
+    hash::BuildHasher,
+    mem::size_of,
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
+};
+use uri::{
+    accepts_mime, belongs_to, is_supported_uri, normalize_uri, region_key, split_rect_fragment, strip_derived_fragment,
+    tint_fragment, ALPHA_MASK_FRAGMENT, GRAYSCALE_FRAGMENT, LINEAR_FRAGMENT, ORIGINAL_FRAGMENT,
 };
-use std::{mem::size_of, path::Path, sync::Arc};
 
-type Entry = Result<Arc<ColorImage>, String>;
+mod apng;
+mod builder;
+mod cache;
+mod chunks;
+mod color;
+mod decode;
+mod decoder;
+mod error;
+#[cfg(all(feature = "async", not(target_arch = "wasm32")))]
+mod future;
+mod metadata;
+mod metrics;
+#[cfg(all(feature = "persistence", not(target_arch = "wasm32")))]
+mod persist;
+mod pool;
+mod queue;
+#[cfg(feature = "retained")]
+mod retained;
+#[cfg(test)]
+mod test_pngs;
+mod uri;
 
-#[derive(Default)]
-pub struct PngLoader {
-    cache: Mutex<HashMap<String, Entry>>,
+pub use apng::AnimatedPng;
+pub use builder::{BrokenImages, DownsampleFilter, OversizedTexture, PngLoaderBuilder};
+pub use cache::{CacheSnapshot, EntryStatus};
+pub use chunks::{is_png_signature, PNG_SIGNATURE};
+pub use decode::{decode_png_from_reader, decode_png_to_color_image, decode_png_to_rgba16, Rgba16Image};
+pub use decoder::{DecodedHeader, Decoder, MinipngDecoder};
+pub use error::PngLoadError;
+#[cfg(all(feature = "async", not(target_arch = "wasm32")))]
+pub use future::LoadFuture;
+pub use metadata::{read_metadata, read_text_metadata, PngMetadata};
+pub use metrics::{ImageStats, PngLoaderMetrics};
+pub use minipng::{BitDepth, ColorType, Error as MinipngError};
+#[cfg(feature = "retained")]
+pub use retained::RetainedPng;
+
+type Entry = Result<CachedImage, Arc<PngLoadError>>;
+type AnimationEntry = Result<Arc<AnimatedPng>, Arc<PngLoadError>>;
+type RegionResult = Result<Arc<ColorImage>, PngLoadError>;
+
+/// The egui image loader for PNG images.
+///
+/// Its caches are hash maps using `S` as their hasher, which defaults to the one used throughout egui. Other
+/// hashers can be used by building the loader with [`PngLoaderBuilder::build_with_hasher`].
+pub struct PngLoader<S = RandomState> {
+    // Everything a background decode touches is shared with it
+    cache: Arc<Mutex<Cache<S>>>,
+    animations: Mutex<StdHashMap<String, AnimationEntry, S>>,
+    rgba16: Arc<Mutex<StdHashMap<String, Arc<Rgba16Image>, S>>>,
+    headers: Mutex<StdHashMap<String, PngMetadata, S>>,
+    /// The bytes of images inserted with lazy decoding, which no byte loader knows about.
+    compressed: Mutex<StdHashMap<String, Arc<[u8]>, S>>,
+    /// When fetching the bytes of each image last failed and why, see [`PngLoaderBuilder::byte_error_backoff`].
+    byte_errors: Mutex<StdHashMap<String, (Instant, LoadError), S>>,
+    metrics: Arc<Metrics>,
+    pool: Arc<BufferPool>,
+    decodes: Arc<DecodeQueue>,
+    decoder: Arc<dyn Decoder>,
+    config: Arc<Config>,
 }
 
 impl PngLoader {
     pub const ID: &'static str = egui::generate_loader_id!(PngLoader);
-}
 
-fn is_supported_uri(uri: &str) -> bool {
-    let Some(ext) = Path::new(uri).extension().and_then(|ext| ext.to_str()) else {
-        return false;
-    };
+    /// The maximum image dimensions accepted by a default loader.
+    pub const DEFAULT_MAX_DIMENSIONS: (u32, u32) = (16384, 16384);
 
-    ext == "png"
-}
+    /// Returns a builder for configuring a new loader.
+    pub fn builder() -> PngLoaderBuilder {
+        PngLoaderBuilder::new()
+    }
 
-fn is_unsupported_mime(mime: &str) -> bool {
-    !mime.contains("png")
+    /// Creates a loader with room in its cache for `capacity` entries.
+    ///
+    /// This is a shortcut for [`PngLoaderBuilder::cache_capacity`], which can be combined with other options.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::builder().cache_capacity(capacity).build()
+    }
+
+    /// Creates a loader that decodes its images with `decoder` instead of minipng.
+    ///
+    /// This is a shortcut for [`PngLoaderBuilder::decoder`], which can be combined with other options.
+    pub fn with_decoder(decoder: Arc<dyn Decoder>) -> Self {
+        Self::builder().decoder(decoder).build()
+    }
 }
 
-fn load_image_bytes(header: &minipng::ImageHeader, bytes: &Bytes) -> Result<ColorImage, minipng::Error> {
-    let mut buffer = vec![0; header.required_bytes_rgba8bpc()];
-    let mut image = minipng::decode_png(bytes, &mut buffer)?;
-    image.convert_to_rgba8bpc()?;
+impl<S: BuildHasher + Clone + Send + Sync + 'static> PngLoader<S> {
+    fn from_config(config: Config, hasher: S) -> Self {
+        let config = Arc::new(config);
+        let pool = Arc::new(BufferPool::new(config.reuse_buffers));
+        let decoder = match &config.decoder {
+            Some(decoder) => decoder.clone(),
+            None => Arc::new(MinipngDecoder::new(config.clone(), pool.clone())),
+        };
+        Self {
+            cache: Arc::new(Mutex::new(Cache::with_capacity_and_hasher(config.cache_capacity, hasher.clone()))),
+            animations: Mutex::new(StdHashMap::with_hasher(hasher.clone())),
+            rgba16: Arc::new(Mutex::new(StdHashMap::with_hasher(hasher.clone()))),
+            headers: Mutex::new(StdHashMap::with_hasher(hasher.clone())),
+            compressed: Mutex::new(StdHashMap::with_hasher(hasher.clone())),
+            byte_errors: Mutex::new(StdHashMap::with_hasher(hasher)),
+            metrics: Default::default(),
+            pool,
+            decodes: Arc::new(DecodeQueue::new(config.clone())),
+            decoder,
+            config,
+        }
+    }
+
+    /// Loads every frame of the animated PNG at `uri`.
+    ///
+    /// Returns `Ok(None)` while the bytes are still being fetched. Static PNGs are loaded as a single frame, so
+    /// callers can treat every PNG the same way. Animations are cached separately from the images returned by
+    /// [`ImageLoader::load`], which only ever contain the default image.
+    pub fn load_animated(&self, ctx: &Context, uri: &str) -> Result<Option<Arc<AnimatedPng>>, LoadError> {
+        let poll = |entry: &AnimationEntry| match entry {
+            Ok(animation) => Ok(Some(animation.clone())),
+            Err(err) => Err(LoadError::Loading(err.to_string())),
+        };
 
-    let size = [image.width() as _, image.height() as _];
-    let pixels = image.pixels();
-    Ok(ColorImage::from_rgba_unmultiplied(size, pixels))
-}
+        let key = normalize_uri(uri);
+        if let Some(entry) = self.animations.lock().get(&*key) {
+            return poll(entry);
+        }
 
-impl ImageLoader for PngLoader {
-    fn id(&self) -> &str {
-        Self::ID
+        match ctx.try_load_bytes(uri) {
+            Ok(BytesPoll::Ready { bytes, mime, .. }) => {
+                if !accepts_mime(mime.as_deref(), &self.config) {
+                    return Err(LoadError::NotSupported);
+                }
+                if is_truncated(&bytes) {
+                    return Err(LoadError::Loading(PngLoadError::Truncated.to_string()));
+                }
+
+                let entry = match decode_header(&bytes) {
+                    Ok(header) => self.config.check_dimensions(header.width(), header.height()).and_then(|()| {
+                        self.config.check_crcs(&bytes)?;
+                        decode_animated(&bytes, &self.config)
+                    }),
+                    // Anything with the signature is still a PNG, so report why it can't be loaded instead of letting
+                    // other loaders try
+                    Err(err) if is_png_signature(&bytes) => Err(err),
+                    Err(_) => return Err(LoadError::NotSupported),
+                };
+                let entry = entry.map(Arc::new).map_err(Arc::new);
+                let result = poll(&entry);
+                self.config.report_error(&key, &entry);
+                if self.config.cache_enabled {
+                    self.animations.lock().insert(key.into_owned(), entry);
+                }
+                result
+            }
+            Ok(BytesPoll::Pending { .. }) => {
+                self.config.bytes_pending(ctx);
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Returns whether `uri` looks like a PNG image to the loader, judging by its extension, or by its MIME type for
+    /// `data:` URIs, without fetching anything. E.g. to tell users right away that a path they entered isn't a PNG.
+    ///
+    /// This takes [extra extensions](PngLoaderBuilder::extension) into account. With
+    /// [content sniffing](PngLoaderBuilder::sniff_content), URIs for which this returns `false` are still loaded if
+    /// their bytes turn out to be a PNG image.
+    pub fn will_handle(&self, uri: &str) -> bool {
+        is_supported_uri(uri, &self.config)
     }
 
-    fn load(&self, ctx: &egui::Context, uri: &str, _: SizeHint) -> ImageLoadResult {
-        if !is_supported_uri(uri) {
+    /// Reads the metadata of the PNG at `uri` without decoding its pixels.
+    ///
+    /// Returns `None` if the bytes are still being fetched, couldn't be fetched, or aren't a PNG image. The metadata
+    /// is remembered until the image is forgotten, so that loads of it that are pending report its true size.
+    pub fn peek_header(&self, ctx: &Context, uri: &str) -> Option<PngMetadata> {
+        let metadata = match ctx.try_load_bytes(uri) {
+            Ok(BytesPoll::Ready { bytes, .. }) => read_metadata(&bytes).ok()?,
+            Ok(BytesPoll::Pending { .. }) | Err(_) => return None,
+        };
+        if self.config.cache_enabled {
+            self.headers.lock().insert(normalize_uri(uri).into_owned(), metadata);
+        }
+        Some(metadata)
+    }
+
+    /// Reads the text stored in the `tEXt` and `iTXt` chunks of the PNG at `uri`, such as its title or author, as
+    /// keyword and text pairs. See [`read_text_metadata`] for which chunks are read.
+    ///
+    /// Returns `None` if the bytes are still being fetched, couldn't be fetched, or aren't a PNG image. Nothing is
+    /// decoded nor cached.
+    pub fn text_metadata(&self, ctx: &Context, uri: &str) -> Option<Vec<(String, String)>> {
+        match ctx.try_load_bytes(uri) {
+            Ok(BytesPoll::Ready { bytes, .. }) => read_text_metadata(&bytes).ok(),
+            Ok(BytesPoll::Pending { .. }) | Err(_) => None,
+        }
+    }
+
+    /// Estimates how many bytes the image at `uri` would take up in the cache at its original size, from its header
+    /// alone, so that it can be weighed against a memory budget before loading it.
+    ///
+    /// Returns `None` in the same cases as [`Self::peek_header`]. The estimate doesn't account for
+    /// [compacted palettes](PngLoaderBuilder::compact_palettes) or [16-bit copies](PngLoaderBuilder::preserve_16_bit).
+    pub fn estimated_decoded_size(&self, ctx: &Context, uri: &str) -> Option<usize> {
+        let header = self.peek_header(ctx, uri)?;
+        (header.width as usize).checked_mul(header.height as usize)?.checked_mul(size_of::<Color32>())
+    }
+
+    /// Starts loading each of `uris` so that they are ready by the time they are first drawn.
+    ///
+    /// URIs whose bytes are still being fetched are skipped; call this again later to pick them up. Already cached
+    /// URIs are left untouched, and errors are cached as usual. With the `parallel` feature, the images are decoded
+    /// concurrently on rayon's global thread pool, if more than one of them isn't cached yet.
+    pub fn preload(&self, ctx: &Context, uris: &[&str]) {
+        let uris: Vec<&str> = {
+            let cache = self.cache.lock();
+            uris.iter()
+                .copied()
+                .filter(|uri| !cache.contains(&(normalize_uri(uri).into_owned(), SizeHint::default())))
+                .collect()
+        };
+
+        #[cfg(feature = "parallel")]
+        if uris.len() > 1 {
+            use rayon::prelude::*;
+            uris.par_iter().for_each(|uri| {
+                let _ = self.load(ctx, uri, SizeHint::default());
+            });
+            return;
+        }
+
+        for uri in uris {
+            let _ = self.load(ctx, uri, SizeHint::default());
+        }
+    }
+
+    /// Decodes `bytes` and caches the image under `uri`, for images that come from somewhere egui's byte loaders
+    /// don't know about, such as a custom archive.
+    ///
+    /// Loading `uri` then finds the image in the cache without fetching any bytes, at any size. The URI still has to
+    /// end in one of the loader's extensions, unless [content sniffing](PngLoaderBuilder::sniff_content) is
+    /// enabled. Any image already cached under `uri` is replaced.
+    ///
+    /// No byte loader can fetch `bytes` again, so they are kept alongside the image until it is forgotten, and the
+    /// image is decoded from them again after it is [invalidated](Self::invalidate) or evicted. They count towards
+    /// [`ImageLoader::byte_size`]. With [lazy decoding](PngLoaderBuilder::lazy_decoding), only the header is checked
+    /// and the image is first decoded once it is loaded.
+    pub fn insert_bytes(&self, uri: &str, bytes: &[u8]) -> Result<(), PngLoadError> {
+        self.forget(uri);
+        if self.config.lazy_decoding.is_none() {
+            self.decode_and_insert(uri, bytes, None)?;
+        } else {
+            let header = self.check_bytes(bytes)?;
+            self.headers.lock().insert(normalize_uri(uri).into_owned(), PngMetadata::from(&header));
+        }
+        self.compressed.lock().insert(normalize_uri(uri).into_owned(), bytes.into());
+        Ok(())
+    }
+
+    /// Registers `bytes` with egui's byte cache under `uri` without copying them, and decodes them into the loader's
+    /// cache right away, e.g. for icons embedded with `include_bytes!` at startup.
+    ///
+    /// The image is cached like any other loaded image, so drawing `uri` finds it without decoding it again, and
+    /// [`ImageLoader::forget`] and [`ImageLoader::byte_size`] treat it as usual. The bytes stay registered with egui
+    /// even if they can't be decoded. With [lazy decoding](PngLoaderBuilder::lazy_decoding), only the header is
+    /// checked, and the image is decoded once it is first loaded.
+    pub fn add_static(&self, ctx: &Context, uri: &str, bytes: Arc<[u8]>) -> Result<(), PngLoadError> {
+        ctx.include_bytes(uri.to_owned(), bytes.clone());
+        self.forget(uri);
+        if self.config.lazy_decoding.is_some() {
+            return self.check_bytes(&bytes).map(drop);
+        }
+        let fingerprint = self.config.detect_changes.then(|| fingerprint(&bytes));
+        self.decode_and_insert(uri, &bytes, fingerprint).map(drop)
+    }
+
+    /// Reads and decodes the PNG file at `path`, bypassing egui's byte loaders, and caches it under the `file://`
+    /// URI of its canonicalized path.
+    ///
+    /// That is the URI egui's file loader uses for absolute paths, so drawing e.g. `file:///abs/path.png`
+    /// afterwards finds the image in the cache. The file is only read again once the image has been forgotten, or
+    /// if it wasn't cached in the first place, because caching is [disabled](PngLoaderBuilder::cache_enabled) or the
+    /// image is over the [entry limit](PngLoaderBuilder::max_entry_bytes).
+    /// Unlike [`ImageLoader::load`], this ignores the loader's extensions and content sniffing settings.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_path(&self, path: &std::path::Path) -> Result<Arc<ColorImage>, PngLoadError> {
+        let uri = format!("file://{}", std::fs::canonicalize(path)?.display());
+        let cached = self.cache.lock().get(&(uri.clone(), SizeHint::default())).cloned();
+        if let Some(Ok(image)) = cached {
+            self.metrics.hit();
+            return Ok(image.color_image());
+        }
+
+        let bytes = std::fs::read(path)?;
+        self.forget(&uri);
+        Ok(self.decode_and_insert(&uri, &bytes, None)?.color_image())
+    }
+
+    /// Reads the PNG image from `reader`, such as an entry of an archive, then decodes it and caches it under `uri`,
+    /// replacing what was cached for it before.
+    ///
+    /// minipng can't decode incrementally, so the whole stream is read into memory first. Unlike with
+    /// [`Self::insert_bytes`], the bytes aren't kept around, so once the image is forgotten or evicted, it has to be
+    /// read again. Drawing `uri` finds it in the cache until then, unless caching is
+    /// [disabled](PngLoaderBuilder::cache_enabled) or the image is over the
+    /// [entry limit](PngLoaderBuilder::max_entry_bytes).
+    pub fn load_reader(&self, uri: &str, mut reader: impl std::io::Read) -> Result<Arc<ColorImage>, PngLoadError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        self.forget(uri);
+        Ok(self.decode_and_insert(uri, &bytes, None)?.color_image())
+    }
+
+    /// Checks that `bytes` hold a complete PNG image that the loader accepts, and returns its header.
+    fn check_bytes(&self, bytes: &[u8]) -> Result<minipng::ImageHeader, PngLoadError> {
+        let header = decode_header(bytes)?;
+        if is_truncated(bytes) {
+            return Err(PngLoadError::Truncated);
+        }
+        self.config.check_dimensions(header.width(), header.height())?;
+        self.config.check_crcs(bytes)?;
+        Ok(header)
+    }
+
+    /// Decodes `bytes` at their original size and caches the image under `uri`, if the cache settings allow it.
+    ///
+    /// Smaller sizes of an image that was cached under `uri` before aren't replaced, so it should be forgotten first.
+    fn decode_and_insert(
+        &self,
+        uri: &str,
+        bytes: &[u8],
+        fingerprint: Option<u64>,
+    ) -> Result<CachedImage, PngLoadError> {
+        let header = self.check_bytes(bytes)?;
+
+        let start = Instant::now();
+        let filter = self.config.downsample_filter_for(uri);
+        let image = if self.config.compact_palettes {
+            load_image_bytes_compact(&header, bytes, SizeHint::default(), filter, &self.config, &self.pool)?
+        } else {
+            load_image_bytes(&header, bytes, SizeHint::default(), filter, &self.config, &self.pool)?.into()
+        };
+        let elapsed = start.elapsed();
+        self.metrics.decoded(elapsed);
+
+        if !self.config.cache_enabled || !self.config.admits(image.size()) {
+            return Ok(image);
+        }
+        let uri = normalize_uri(uri).into_owned();
+        self.headers.lock().insert(uri.clone(), PngMetadata::from(&header));
+        let (key, entry) = ((uri, SizeHint::default()), Ok(image.clone()));
+        let stats = ImageStats {
+            decode_duration: elapsed,
+            compressed_size: bytes.len(),
+            decoded_size: entry_byte_size(&entry),
+            budget_downscale: 1,
+        };
+        let mut cache = self.cache.lock();
+        let evicted = cache.insert(key.clone(), entry, fingerprint, self.config.cache_limits());
+        cache.set_stats(&key, stats);
+        drop(cache);
+        self.config.report_evictions(evicted);
+        Ok(image)
+    }
+
+    /// Marks every cached image of `uri` as outdated, so that the next load decodes it again.
+    ///
+    /// Unlike [`ImageLoader::forget`], the outdated images are still returned until they are replaced, while
+    /// [background decoding](PngLoaderBuilder::background_decoding) catches up. The bytes are fetched the same way
+    /// as before, so egui's cached copy is reused unless it was forgotten too.
+    ///
+    /// This doesn't change what an [`egui::Image`] shows: egui's texture loader keeps the texture it uploaded and
+    /// never loads the image again. To reload an image, load it on the loader returned by [`install`] or with
+    /// [`PngLoader::load_async`] until the new version is ready, then upload it with [`Context::load_texture`] in
+    /// place of the old texture.
+    pub fn invalidate(&self, uri: &str) {
+        let uri = &*normalize_uri(uri);
+        self.cache.lock().invalidate(|(key, _)| belongs_to(key, uri));
+    }
+
+    /// Loads each of `uris` at its original size, exactly as [`ImageLoader::load`] would, and returns the results
+    /// in the same order.
+    pub fn load_many(&self, ctx: &Context, uris: &[&str]) -> Vec<ImageLoadResult> {
+        uris.iter().map(|uri| self.load(ctx, uri, SizeHint::default())).collect()
+    }
+
+    /// Like [`load_many`](Self::load_many), but only returns the images that are ready, along with their URIs.
+    ///
+    /// Images that are still loading or failed to load are left out.
+    pub fn load_ready<'a>(&self, ctx: &Context, uris: &[&'a str]) -> Vec<(&'a str, Arc<ColorImage>)> {
+        uris.iter()
+            .filter_map(|&uri| match self.load(ctx, uri, SizeHint::default()) {
+                Ok(ImagePoll::Ready { image }) => Some((uri, image)),
+                Ok(ImagePoll::Pending { .. }) | Err(_) => None,
+            })
+            .collect()
+    }
+
+    /// Forgets every cached image whose URI starts with `prefix`, such as `"assets/level3/"`.
+    pub fn forget_prefix(&self, prefix: &str) {
+        let prefix = &*normalize_uri(prefix);
+        let mut cache = self.cache.lock();
+        cache.retain(|(uri, _), _| !uri.starts_with(prefix));
+        cache.cancel_decodes(|(uri, _)| uri.starts_with(prefix));
+        drop(cache);
+        self.animations.lock().retain(|uri, _| !uri.starts_with(prefix));
+        self.rgba16.lock().retain(|uri, _| !uri.starts_with(prefix));
+        self.headers.lock().retain(|uri, _| !uri.starts_with(prefix));
+        self.compressed.lock().retain(|uri, _| !uri.starts_with(prefix));
+        self.byte_errors.lock().retain(|uri, _| !uri.starts_with(prefix));
+    }
+
+    /// Releases memory that the loader retained after images were forgotten or evicted, without forgetting anything
+    /// else, e.g. after unloading a level.
+    ///
+    /// This shrinks the loader's maps to fit what they hold and frees the
+    /// [reused buffers](PngLoaderBuilder::reuse_buffers). Decoded images are always allocated to their exact size, so
+    /// they have nothing to give back.
+    pub fn trim(&self) {
+        self.cache.lock().shrink_to_fit();
+        self.animations.lock().shrink_to_fit();
+        self.rgba16.lock().shrink_to_fit();
+        self.headers.lock().shrink_to_fit();
+        self.compressed.lock().shrink_to_fit();
+        self.byte_errors.lock().shrink_to_fit();
+        self.pool.clear();
+    }
+
+    /// Forgets the cached images that haven't been loaded for longer than `older_than`, e.g. when called every few
+    /// seconds to keep a long-running app's memory in check, and returns how many were forgotten.
+    ///
+    /// Unlike [cache limits](PngLoaderBuilder::max_cache_bytes), this doesn't depend on how much the cache holds, and
+    /// unlike [lazy decoding](PngLoaderBuilder::lazy_decoding), it counts time rather than frames. Each size of an
+    /// image counts separately, and failures are kept so that they aren't retried.
+    pub fn forget_stale(&self, older_than: Duration) -> usize {
+        self.cache.lock().forget_stale(Instant::now(), older_than)
+    }
+
+    /// Forgets every cached error while keeping successfully decoded images, so failed loads are retried.
+    pub fn clear_errors(&self) {
+        self.cache.lock().retain(|_, entry| entry.is_ok());
+        self.animations.lock().retain(|_, entry| entry.is_ok());
+        self.byte_errors.lock().clear();
+    }
+
+    /// Returns the full-precision copy of the 16-bit image at `uri`.
+    ///
+    /// This is only available once the image has been loaded by a loader built with
+    /// [`PngLoaderBuilder::preserve_16_bit`], and only for images that actually have 16 bits per channel.
+    pub fn rgba16(&self, uri: &str) -> Option<Arc<Rgba16Image>> {
+        self.rgba16.lock().get(&*normalize_uri(uri)).cloned()
+    }
+
+    /// Returns the color type that the PNG at `uri` was stored with, before being converted to RGBA.
+    ///
+    /// This is known once the image's header was read by [`ImageLoader::load`], even if decoding it failed later
+    /// on. Parts of sprite sheets report the color type of the whole sheet.
+    pub fn color_type(&self, uri: &str) -> Option<ColorType> {
+        self.header(uri).map(|header| header.color_type)
+    }
+
+    /// Returns the bit depth that the PNG at `uri` was stored with, like [`Self::color_type`].
+    pub fn bit_depth(&self, uri: &str) -> Option<BitDepth> {
+        self.header(uri).map(|header| header.bit_depth)
+    }
+
+    /// Returns the texture options that the image at `uri` is best drawn with.
+    ///
+    /// egui picks the filtering of textures when drawing images rather than when loading them, so this is only a
+    /// suggestion to pass along, configured with [`PngLoaderBuilder::texture_options`] and
+    /// [`PngLoaderBuilder::nearest_filter_max_side`]. The latter needs the image's header to have been read.
+    ///
+    /// ```no_run
+    /// # let ctx = egui::Context::default();
+    /// # let loader = egui_minipng::install(&ctx);
+    /// # egui::CentralPanel::default().show(&ctx, |ui| {
+    /// let uri = "file://sprites/hero.png";
+    /// ui.add(egui::Image::new(uri).texture_options(loader.texture_options_for(uri)));
+    /// # });
+    /// ```
+    pub fn texture_options_for(&self, uri: &str) -> TextureOptions {
+        let source = split_rect_fragment(uri).map_or(uri, |(source, _)| source);
+        let options = &self.config.texture_options;
+        if let Some(options) = options.get(&*normalize_uri(uri)).or_else(|| options.get(&*normalize_uri(source))) {
+            return *options;
+        }
+
+        let small = |max_side| self.header(source).is_some_and(|header| header.width.max(header.height) <= max_side);
+        if self.config.nearest_filter_max_side.is_some_and(small) {
+            TextureOptions::NEAREST
+        } else {
+            TextureOptions::default()
+        }
+    }
+
+    /// Returns what was read from the header of the image at `uri`, or of the whole sprite sheet for parts of one.
+    fn header(&self, uri: &str) -> Option<PngMetadata> {
+        let source = split_rect_fragment(uri).map_or(uri, |(source, _)| source);
+        self.headers.lock().get(&*normalize_uri(source)).copied()
+    }
+
+    /// Returns the loader's cache hit and decode statistics so far.
+    pub fn metrics(&self) -> PngLoaderMetrics {
+        self.metrics.snapshot()
+    }
+
+    /// Returns how long the image cached under `uri` took to decode and how large it is compared to its PNG bytes,
+    /// e.g. to find the assets worth exporting at a lower resolution.
+    ///
+    /// These are the stats of the full-size image when it is cached, and of a smaller size of it otherwise. Images
+    /// that failed to decode, and parts of sprite sheets, have none.
+    pub fn stats(&self, uri: &str) -> Option<ImageStats> {
+        self.cache.lock().stats(&normalize_uri(uri))
+    }
+
+    /// Returns the most memory the loader's image cache has used at once, as counted by [`ImageLoader::byte_size`],
+    /// since the loader was created or [`Self::reset_peak`] was last called.
+    ///
+    /// This is sampled after each image is inserted and the oldest ones are evicted to make room for it, so a cache
+    /// kept within [`PngLoaderBuilder::max_cache_bytes`] only peaks above it when a single image is larger than that.
+    /// Animations and 16-bit copies aren't counted.
+    pub fn peak_byte_size(&self) -> usize {
+        self.cache.lock().peak_byte_size()
+    }
+
+    /// Resets the [peak memory usage](Self::peak_byte_size) to what the image cache uses now.
+    pub fn reset_peak(&self) {
+        self.cache.lock().reset_peak();
+    }
+
+    /// Returns the number of cached entries, counting each size of an image separately.
+    pub fn len(&self) -> usize {
+        self.cache.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns whether any result for `uri` is cached, successful or not.
+    pub fn is_cached(&self, uri: &str) -> bool {
+        self.entry_status(uri).is_some()
+    }
+
+    /// Loads the image at `uri` at its original size, even if the loader would downscale it to fit
+    /// [textures](PngLoaderBuilder::oversized_textures) or [a byte budget](PngLoaderBuilder::max_decoded_bytes_per_load),
+    /// e.g. to export it or inspect it pixel by pixel.
+    ///
+    /// Images that [`ImageLoader::load`] already has at their original size are shared with it. Others are decoded
+    /// on the calling thread and cached separately under `{uri}#original`, so that they are forgotten along with the
+    /// image. The result may still be too large for a texture, so it is meant to be read rather than drawn.
+    pub fn load_full(&self, ctx: &Context, uri: &str) -> ImageLoadResult {
+        let sniffing = !is_supported_uri(uri, &self.config);
+        if sniffing && !self.config.sniff_content {
             return Err(LoadError::NotSupported);
         }
+        let key_uri = normalize_uri(uri);
+        let bytes = match ctx.try_load_bytes(uri) {
+            Ok(BytesPoll::Ready { mime, .. }) if !accepts_mime(mime.as_deref(), &self.config) => {
+                return Err(LoadError::NotSupported);
+            }
+            Ok(BytesPoll::Ready { bytes, .. }) => bytes,
+            Ok(BytesPoll::Pending { size }) => {
+                self.config.bytes_pending(ctx);
+                return Ok(ImagePoll::Pending { size });
+            }
+            Err(err) => {
+                return match self.load_inserted(&key_uri, SizeHint::default()) {
+                    Some(result) => result,
+                    None if sniffing => Err(LoadError::NotSupported),
+                    None => Err(err),
+                };
+            }
+        };
+        let fingerprint = self.config.detect_changes.then(|| fingerprint(&bytes));
+
+        let original = (format!("{key_uri}{ORIGINAL_FRAGMENT}"), SizeHint::default());
+        if let Some(entry) = self.cache.lock().get_fresh(&original, fingerprint) {
+            return poll_entry(entry);
+        }
+        let (header, png_header) = match self.read_header(&key_uri, &bytes, fingerprint) {
+            Ok(header) => header,
+            Err(result) => return result,
+        };
+        let size = [header.width as usize, header.height as usize];
+        let full = self.cache.lock().get_fresh(&(key_uri.to_string(), SizeHint::default()), fingerprint).cloned();
+        let full = full.and_then(Result::ok).map(|full| full.color_image());
+        if let Some(image) = full.filter(|full| full.size == size) {
+            self.metrics.hit();
+            return Ok(ImagePoll::Ready { image });
+        }
+
+        let start = Instant::now();
+        let filter = self.config.downsample_filter_for(&key_uri);
+        let attempt = || {
+            self.config.check_dimensions(header.width, header.height)?;
+            self.config.check_crcs(&bytes)?;
+            match &png_header {
+                Some(header) if self.config.compact_palettes => {
+                    load_image_bytes_compact(header, &bytes, SizeHint::default(), filter, &self.config, &self.pool)
+                }
+                Some(header) => load_image_bytes(header, &bytes, SizeHint::default(), filter, &self.config, &self.pool)
+                    .map(Into::into),
+                None => self.decoder.decode(&bytes).map(Into::into),
+            }
+        };
+        let entry = attempt().map_err(Arc::new);
+        self.metrics.decoded(start.elapsed());
+        self.config.report_error(&key_uri, &entry);
+        if self.config.cache_enabled && (self.config.admits(size) || entry.is_err()) {
+            let evicted = self.cache.lock().insert(original, entry.clone(), fingerprint, self.config.cache_limits());
+            self.config.report_evictions(evicted);
+        }
+        poll_entry(&entry)
+    }
+
+    /// Loads a grayscale version of the image at `uri`, e.g. to show it as disabled.
+    ///
+    /// This is loaded like [`ImageLoader::load`] would, then desaturated with the Rec. 709 luma weights while keeping
+    /// its alpha. The grayscale version is cached separately, so it can be shown alongside the colored one, and is
+    /// forgotten along with it.
+    pub fn load_grayscale(&self, ctx: &Context, uri: &str, size_hint: SizeHint) -> ImageLoadResult {
+        self.load_derived(ctx, uri, GRAYSCALE_FRAGMENT, size_hint, grayscale)
+    }
+
+    /// Loads a version of the image at `uri` with its colors converted from sRGB to linear space, e.g. for textures
+    /// sampled by shaders that expect linear colors.
+    ///
+    /// This is loaded like [`ImageLoader::load`] would, then has the sRGB transfer function undone on each color
+    /// channel, leaving alpha untouched. The linear values are still stored with 8 bits per channel, which loses
+    /// precision in dark colors. The linear version is cached separately and is forgotten along with the image, while
+    /// [`ImageLoader::load`] keeps returning the usual sRGB colors.
+    pub fn load_linear(&self, ctx: &Context, uri: &str, size_hint: SizeHint) -> ImageLoadResult {
+        self.load_derived(ctx, uri, LINEAR_FRAGMENT, size_hint, linearizer())
+    }
+
+    /// Loads the alpha mask of the image at `uri`: a white image with the same alpha, e.g. for glyph or icon masks
+    /// that are tinted when painted.
+    ///
+    /// This is loaded like [`ImageLoader::load`] would, then has its colors dropped. egui has no single-channel
+    /// images, so the mask still takes up four bytes per pixel. It is cached separately from the full-color image,
+    /// and is forgotten along with it.
+    pub fn load_alpha_mask(&self, ctx: &Context, uri: &str, size_hint: SizeHint) -> ImageLoadResult {
+        self.load_derived(ctx, uri, ALPHA_MASK_FRAGMENT, size_hint, alpha_mask)
+    }
+
+    /// Loads a version of the image at `uri` with its colors and alpha multiplied by `tint`, e.g. to recolor a white
+    /// icon to match a theme.
+    ///
+    /// This is loaded like [`ImageLoader::load`] would, which keeps the untinted image cached to derive other tints
+    /// from. Each tint is cached separately, and is forgotten along with the image.
+    pub fn load_tinted(&self, ctx: &Context, uri: &str, tint: Color32, size_hint: SizeHint) -> ImageLoadResult {
+        self.load_derived(ctx, uri, &tint_fragment(tint), size_hint, |pixel| color::tint(pixel, tint))
+    }
+
+    /// Loads the image at `uri` like [`ImageLoader::load`] would, then caches a version of it with `map` applied to
+    /// each pixel under the URI followed by `fragment`, so that it is forgotten along with the image.
+    fn load_derived(
+        &self,
+        ctx: &Context,
+        uri: &str,
+        fragment: &str,
+        size_hint: SizeHint,
+        map: impl Fn(Color32) -> Color32,
+    ) -> ImageLoadResult {
+        let image = match self.load_image(ctx, uri, size_hint) {
+            Ok(ImagePoll::Ready { image }) => image,
+            result => return self.with_fallback(result),
+        };
+
+        let key = (format!("{}{fragment}", normalize_uri(uri)), size_hint);
+        let fingerprint = self.source_fingerprint(ctx, uri);
+        if let Some(entry) = self.cache.lock().get_fresh(&key, fingerprint) {
+            return poll_entry(entry);
+        }
+        let pixels = image.pixels.iter().copied().map(map).collect();
+        self.insert_derived(key, ColorImage { size: image.size, pixels }, fingerprint)
+    }
+
+    /// Replaces failures with the [fallback image](PngLoaderBuilder::fallback), if there is one.
+    fn with_fallback(&self, result: ImageLoadResult) -> ImageLoadResult {
+        match (result, &self.config.fallback) {
+            (Err(LoadError::Loading(_)), Some(fallback)) => Ok(ImagePoll::Ready { image: fallback.clone() }),
+            (result, _) => result,
+        }
+    }
+
+    /// Returns every URI that something is cached for, once each and in no particular order.
+    ///
+    /// The URIs are normalized, and include those of animations and of parts of sprite sheets. Passing them to
+    /// [`Self::preload`] on the next start warms the cache up with the same images.
+    pub fn cached_uris(&self) -> Vec<String> {
+        // Derived versions, such as grayscale ones and alpha masks, are loaded separately, and are listed as the image
+        // they are derived from
+        let mut uris: HashSet<String> =
+            self.cache.lock().entries().map(|((uri, _), _)| strip_derived_fragment(uri).to_owned()).collect();
+        uris.extend(self.animations.lock().keys().cloned());
+        uris.extend(self.compressed.lock().keys().cloned());
+        uris.into_iter().collect()
+    }
+
+    /// Takes a snapshot of the image cache: every decoded image and cached failure, at every size it was loaded at.
+    ///
+    /// Along with [`Self::restore`], this lets tests set up and compare the loader's state without loading anything.
+    /// Animations, 16-bit copies and image headers aren't part of it.
+    pub fn snapshot(&self) -> CacheSnapshot {
+        let cache = self.cache.lock();
+        let entries =
+            cache.fingerprinted_entries().map(|(key, entry, fingerprint)| (key.clone(), entry.clone(), fingerprint));
+        CacheSnapshot { entries: entries.collect() }
+    }
 
+    /// Replaces the contents of the image cache with those of `snapshot`, as taken by [`Self::snapshot`], possibly
+    /// from another loader.
+    ///
+    /// In-flight background decodes are cancelled. The restored entries are inserted within the loader's cache
+    /// limits as usual, so some may be evicted right away.
+    pub fn restore(&self, snapshot: &CacheSnapshot) {
+        let limits = self.config.cache_limits();
+        let mut evicted = Vec::new();
         let mut cache = self.cache.lock();
-        if let Some(entry) = cache.get(uri).cloned() {
-            match entry {
-                Ok(image) => Ok(ImagePoll::Ready { image }),
-                Err(err) => Err(LoadError::Loading(err)),
+        cache.clear();
+        for (key, entry, fingerprint) in &snapshot.entries {
+            evicted.extend(cache.insert(key.clone(), entry.clone(), *fingerprint, limits));
+        }
+        drop(cache);
+        self.config.report_evictions(evicted);
+    }
+
+    /// Returns whether `uri` was loaded or failed to load, or `None` if nothing is cached for it.
+    pub fn entry_status(&self, uri: &str) -> Option<EntryStatus> {
+        let uri = &*normalize_uri(uri);
+        let cache = self.cache.lock();
+        let mut status = None;
+        for ((key, _), entry) in cache.entries() {
+            if key == uri {
+                match entry {
+                    Ok(_) => return Some(EntryStatus::Loaded),
+                    Err(_) => status = Some(EntryStatus::Failed),
+                }
+            }
+        }
+        status
+    }
+
+    /// Returns the width and height of the image cached under `uri`, without handing out the image itself, or `None`
+    /// if it isn't cached or failed to load.
+    ///
+    /// This is the size of the full-size image when it is cached, and of a smaller size of it otherwise. Unlike
+    /// [`Self::peek_header`], it is the size the image was actually decoded at, e.g. after being downscaled to fit a
+    /// texture.
+    pub fn dimensions(&self, uri: &str) -> Option<[usize; 2]> {
+        self.cache.lock().dimensions(&normalize_uri(uri))
+    }
+
+    /// Returns whether the cached image at `uri` has any pixels that aren't fully opaque, or `None` if it isn't
+    /// cached.
+    ///
+    /// This is about the decoded pixels rather than the image format, so an RGBA image whose pixels are all opaque
+    /// reports `false`, while an indexed image with transparent palette entries in use reports `true`. Only the first
+    /// call for an image scans its pixels.
+    pub fn has_transparency(&self, uri: &str) -> Option<bool> {
+        self.cache.lock().has_transparency(&normalize_uri(uri))
+    }
+
+    /// Loads the `rect` part of the image at `source`, which is decoded and cached in full so that every part of it
+    /// can be cropped from the same decoded image.
+    fn load_sub_region(
+        &self,
+        ctx: &Context,
+        uri: &str,
+        source: &str,
+        rect: [usize; 4],
+        size_hint: SizeHint,
+    ) -> ImageLoadResult {
+        let image = match self.load_image(ctx, source, SizeHint::default())? {
+            ImagePoll::Ready { image } => image,
+            pending @ ImagePoll::Pending { .. } => return Ok(pending),
+        };
+
+        // Parts of an image are decoded from the same bytes as the whole image
+        let fingerprint = self.source_fingerprint(ctx, source);
+
+        // Rectangles are given in pixels of the original image, which may have been downscaled to fit a texture or
+        // the byte budget. Those entirely outside of it fail, while the others are clipped to it.
+        let header = self.headers.lock().get(&*normalize_uri(source)).copied();
+        let original = header.map_or(image.size, |header| [header.width as usize, header.height as usize]);
+        let Some(clipped) = crop_size(original, rect) else {
+            let [x, y, width, height] = rect;
+            let entry = Err(Arc::new(PngLoadError::RegionOutOfBounds {
+                name: format!("{x},{y},{width},{height}"),
+                width: original[0] as u32,
+                height: original[1] as u32,
+            }));
+            self.config.report_error(uri, &entry);
+            if self.config.cache_enabled {
+                let key = (uri.to_owned(), SizeHint::default());
+                let evicted = self.cache.lock().insert(key, entry.clone(), fingerprint, self.config.cache_limits());
+                self.config.report_evictions(evicted);
             }
+            return poll_entry(&entry);
+        };
+        let rect = scale_rect([rect[0], rect[1], clipped[0], clipped[1]], original, image.size);
+        let size = crop_size(image.size, rect).expect("scaled rects keep a size");
+
+        let target = target_size(size, size_hint);
+        let key = if target == size { (uri.to_owned(), SizeHint::default()) } else { (uri.to_owned(), size_hint) };
+        if let Some(entry) = self.cache.lock().get_fresh(&key, fingerprint) {
+            return poll_entry(entry);
+        }
+
+        let cropped = crop(&image, rect).expect("crop has a size");
+        let image =
+            if target == size { cropped } else { downsample(&cropped, target, self.config.downsample_filter_for(uri)) };
+        self.insert_derived(key, image, fingerprint)
+    }
+
+    /// Loads the named `regions` of the atlas image at `uri`, such as the sprites of a sprite sheet, in pixels.
+    ///
+    /// The atlas is decoded and cached in full once, like [`ImageLoader::load`] would, then each region is cropped
+    /// out of it and cached under `{uri}#region={name}`, so that regions are forgotten along with the atlas. The
+    /// results are returned in the same order as `regions`, and a region that doesn't lie entirely within the atlas
+    /// fails with [`PngLoadError::RegionOutOfBounds`] without affecting the others. Returns `Ok(None)` while the atlas
+    /// is still loading, and the atlas's error if it fails to load, even if the loader has a
+    /// [fallback image](PngLoaderBuilder::fallback).
+    pub fn load_atlas(
+        &self,
+        ctx: &Context,
+        uri: &str,
+        regions: &[(String, egui::Rect)],
+    ) -> Result<Option<Vec<RegionResult>>, LoadError> {
+        // The fallback image stands in for whole images only, so atlases that fail to load fail every region
+        let atlas = match self.load_image(ctx, uri, SizeHint::default())? {
+            ImagePoll::Ready { image } => image,
+            ImagePoll::Pending { .. } => return Ok(None),
+        };
+        let fingerprint = self.source_fingerprint(ctx, uri);
+
+        // Like with `load_sub_region`, regions are given in pixels of the original atlas, which may have been
+        // downscaled to fit a texture or the byte budget
+        let header = self.headers.lock().get(&*normalize_uri(uri)).copied();
+        let original = header.map_or(atlas.size, |header| [header.width as usize, header.height as usize]);
+
+        let region = |(name, rect): &(String, egui::Rect)| {
+            let key = (region_key(uri, name), SizeHint::default());
+            if let Some(Ok(image)) = self.cache.lock().get_fresh(&key, fingerprint) {
+                return Ok(image.color_image());
+            }
+
+            let [width, height] = original;
+            let (min, max) = (rect.min.round(), rect.max.round());
+            if !(min.x >= 0.0 && min.y >= 0.0 && min.x < max.x && min.y < max.y)
+                || max.x > width as f32
+                || max.y > height as f32
+            {
+                return Err(PngLoadError::RegionOutOfBounds {
+                    name: name.clone(),
+                    width: width as u32,
+                    height: height as u32,
+                });
+            }
+
+            let rect = [min.x as usize, min.y as usize, (max.x - min.x) as usize, (max.y - min.y) as usize];
+            let rect = scale_rect(rect, original, atlas.size);
+            let entry = CachedImage::from(crop(&atlas, rect).expect("region lies within the atlas"));
+            let image = entry.color_image();
+            if self.config.cache_enabled && self.config.admits(image.size) {
+                let evicted = self.cache.lock().insert(key, Ok(entry), fingerprint, self.config.cache_limits());
+                self.config.report_evictions(evicted);
+            }
+            Ok(image)
+        };
+        Ok(Some(regions.iter().map(region).collect()))
+    }
+
+    /// Returns the fingerprint that images derived from the image at `source` are cached with.
+    fn source_fingerprint(&self, ctx: &Context, source: &str) -> Option<u64> {
+        match ctx.try_load_bytes(source) {
+            Ok(BytesPoll::Ready { bytes, .. }) if self.config.detect_changes => Some(fingerprint(&bytes)),
+            _ => None,
+        }
+    }
+
+    /// Caches an image derived from another cached image, such as a smaller size of it, and returns it.
+    fn insert_derived(&self, key: Key, image: ColorImage, fingerprint: Option<u64>) -> ImageLoadResult {
+        let admitted = self.config.admits(image.size);
+        let entry = Ok(CachedImage::from(image));
+        let poll = poll_entry(&entry);
+        if self.config.cache_enabled && admitted {
+            let evicted = self.cache.lock().insert(key, entry, fingerprint, self.config.cache_limits());
+            self.config.report_evictions(evicted);
+        }
+        poll
+    }
+
+    /// Reads the header of the image in `bytes`, which were fetched for `key_uri`, or returns what loading the image
+    /// results in if that fails.
+    ///
+    /// The built-in decoder handles the PNG-specific options, which need the full PNG header, so that is returned
+    /// too unless the loader has a custom decoder.
+    fn read_header(
+        &self,
+        key_uri: &str,
+        bytes: &[u8],
+        fingerprint: Option<u64>,
+    ) -> Result<(DecodedHeader, Option<minipng::ImageHeader>), ImageLoadResult> {
+        let header = if self.config.decoder.is_none() {
+            decode_header(bytes).and_then(|header| {
+                if is_truncated(bytes) {
+                    return Err(PngLoadError::Truncated);
+                }
+                Ok((DecodedHeader { width: header.width(), height: header.height() }, Some(header)))
+            })
         } else {
-            match ctx.try_load_bytes(uri) {
-                Ok(BytesPoll::Ready { bytes, mime, .. }) => {
-                    if mime.as_deref().is_some_and(is_unsupported_mime) {
-                        return Err(LoadError::NotSupported);
+            self.decoder.header(bytes).map(|header| (header, None))
+        };
+        match header {
+            Ok(header) => Ok(header),
+            Err(_) if !self.decoder.recognizes(bytes) => Err(Err(LoadError::NotSupported)),
+            // Data that is cut short comes from truncated downloads rather than corrupt images, and may be replaced by
+            // a complete download later, so this isn't cached
+            Err(PngLoadError::Truncated) => Err(Err(LoadError::Loading(PngLoadError::Truncated.to_string()))),
+            // Anything else in the decoder's format is still an image, so report why it can't be loaded instead of
+            // letting other loaders try. The failure is cached under the full-size key, which every size hint maps to.
+            Err(err) => {
+                let key = (key_uri.to_owned(), SizeHint::default());
+                if let Some(entry) = self.cache.lock().get_fresh(&key, fingerprint) {
+                    return Err(poll_entry(entry));
+                }
+
+                let entry = Err(Arc::new(err));
+                self.config.report_error(key_uri, &entry);
+                if self.config.cache_enabled {
+                    let evicted = self.cache.lock().insert(key, entry.clone(), fingerprint, self.config.cache_limits());
+                    self.config.report_evictions(evicted);
+                }
+                Err(poll_entry(&entry))
+            }
+        }
+    }
+
+    /// Loads `uri` exactly like [`ImageLoader::load`], except that failures are never replaced by the
+    /// [fallback image](PngLoaderBuilder::fallback).
+    fn load_image(&self, ctx: &Context, uri: &str, size_hint: SizeHint) -> ImageLoadResult {
+        let sniffing = !is_supported_uri(uri, &self.config);
+        if sniffing && !self.config.sniff_content {
+            return Err(LoadError::NotSupported);
+        }
+        if let Some(idle_frames) = self.config.lazy_decoding {
+            self.cache.lock().drop_idle(ctx.cumulative_pass_nr(), idle_frames);
+        }
+
+        // Equivalent spellings of a URI share their cache entries, but bytes are still fetched with the URI as given
+        // When watching for changes, the bytes have to be fetched and compared before anything cached is used
+        let key_uri = normalize_uri(uri);
+        if !self.config.detect_changes {
+            if let Some(entry) = self.cache.lock().get(&(key_uri.to_string(), size_hint)) {
+                self.metrics.hit();
+                #[cfg(feature = "log")]
+                log::debug!("cache hit for {key_uri} at {}", HintLabel(size_hint));
+                return poll_entry(entry);
+            }
+        }
+
+        // Parts of sprite sheets are cropped out of the whole image, e.g. `sheet.png#rect=16,32,48,48`
+        match split_rect_fragment(uri) {
+            Some((source, Some(rect))) => return self.load_sub_region(ctx, &key_uri, source, rect, size_hint),
+            Some((source, None)) => return self.load_image(ctx, source, size_hint),
+            None => {}
+        }
+
+        if let Some(err) = self.recent_byte_error(&key_uri) {
+            return Err(err);
+        }
+
+        match ctx.try_load_bytes(uri) {
+            Ok(BytesPoll::Ready { bytes, mime, .. }) => {
+                if !accepts_mime(mime.as_deref(), &self.config) {
+                    return Err(LoadError::NotSupported);
+                }
+
+                let fingerprint = self.config.detect_changes.then(|| fingerprint(&bytes));
+                let (header, png_header) = match self.read_header(&key_uri, &bytes, fingerprint) {
+                    Ok(header) => header,
+                    Err(result) => return result,
+                };
+                if let Some(png_header) = png_header.as_ref().filter(|_| self.config.cache_enabled) {
+                    self.headers.lock().insert(key_uri.to_string(), PngMetadata::from(png_header));
+                }
+
+                // Images that don't fit in a texture are either rejected or downscaled to fit
+                let size = [header.width as usize, header.height as usize];
+                let max_texture_side =
+                    self.config.max_texture_side.unwrap_or_else(|| ctx.input(|i| i.max_texture_side));
+                let mut target = target_size(size, size_hint);
+                let mut decode_hint = size_hint;
+                let oversized = target[0].max(target[1]) > max_texture_side;
+                if oversized && self.config.oversized_textures == OversizedTexture::Downscale {
+                    decode_hint = SizeHint::Size(max_texture_side as u32, max_texture_side as u32);
+                    target = target_size(size, decode_hint);
+                }
+                let budget_downscale = self.config.budget_downscale(target);
+                if budget_downscale > 1 {
+                    let [width, height] = target.map(|side| side.div_ceil(budget_downscale) as u32);
+                    decode_hint = SizeHint::Size(width, height);
+                    target = target_size(size, decode_hint);
+                }
+                let filter = self.config.downsample_filter_for(&key_uri);
+
+                // Hints that don't call for any downscaling all share the full-size entry
+                let key = if target == size {
+                    (key_uri.to_string(), SizeHint::default())
+                } else {
+                    (key_uri.to_string(), size_hint)
+                };
+                if let Some(entry) = self.cache.lock().get_fresh(&key, fingerprint) {
+                    self.metrics.hit();
+                    #[cfg(feature = "log")]
+                    log::debug!("cache hit for {key_uri} at {}", HintLabel(size_hint));
+                    return poll_entry(entry);
+                }
+
+                // Smaller sizes, such as thumbnails, are downscaled from the full-size image when it is already cached
+                // instead of decoding the bytes again, unless it was itself downscaled to fit the byte budget
+                if target != size {
+                    let full_key = (key_uri.to_string(), SizeHint::default());
+                    let full = self.cache.lock().get_fresh(&full_key, fingerprint).cloned();
+                    let full = full.and_then(Result::ok).map(|full| full.color_image());
+                    if let Some(full) = full.filter(|full| full.size[0] >= target[0] && full.size[1] >= target[1]) {
+                        self.metrics.hit();
+                        #[cfg(feature = "log")]
+                        log::debug!(
+                            "cache hit for {key_uri} at full size, downscaling it for {}",
+                            HintLabel(size_hint)
+                        );
+                        return self.insert_derived(key, downsample(&full, target, filter), fingerprint);
                     }
+                }
+
+                // Decode without holding the cache lock, while making sure other callers wait for us instead of
+                // decoding the same image again. Invalidated images are still shown until their replacement is ready.
+                let pending = || match self.cache.lock().get_stale(&key) {
+                    Some(entry) => poll_entry(entry),
+                    None => Ok(ImagePoll::Pending { size: Some(egui::vec2(size[0] as f32, size[1] as f32)) }),
+                };
+                let Some(guard) = DecodeGuard::begin(&self.cache, &key) else {
+                    return pending();
+                };
+                #[cfg(feature = "log")]
+                log::debug!("cache miss for {key_uri} at {}, decoding it", HintLabel(size_hint));
+
+                let cancelled = guard.cancellation();
+                let cached = self.config.cache_enabled && self.config.admits(target);
+                let background = self.config.decodes_in_background() && cached;
+                let decode = {
+                    let (rgba16_images, metrics, pool, config, decoder) = (
+                        self.rgba16.clone(),
+                        self.metrics.clone(),
+                        self.pool.clone(),
+                        self.config.clone(),
+                        self.decoder.clone(),
+                    );
+                    let uri = key_uri.into_owned();
+                    move || {
+                        let attempt = || {
+                            config.check_dimensions(header.width, header.height)?;
+                            config.check_crcs(&bytes)?;
+                            if oversized && config.oversized_textures == OversizedTexture::Reject {
+                                return Err(PngLoadError::TooLargeForTexture {
+                                    width: header.width,
+                                    height: header.height,
+                                    max_side: max_texture_side,
+                                });
+                            }
+
+                            match &png_header {
+                                // Only indexed images are kept compact, so others still get their 16-bit copies
+                                Some(png_header)
+                                    if config.compact_palettes && png_header.color_type() == ColorType::Indexed =>
+                                {
+                                    return load_image_bytes_compact(
+                                        png_header,
+                                        &bytes,
+                                        decode_hint,
+                                        filter,
+                                        &config,
+                                        &pool,
+                                    );
+                                }
+                                Some(png_header) if config.preserve_16_bit => {
+                                    let (image, rgba16) = load_image_bytes_rgba16(
+                                        png_header,
+                                        &bytes,
+                                        decode_hint,
+                                        filter,
+                                        &config,
+                                        &pool,
+                                    )?;
+                                    if let Some(rgba16) = rgba16.filter(|_| cached && !guard.is_cancelled()) {
+                                        rgba16_images.lock().insert(uri.clone(), Arc::new(rgba16));
+                                    }
+                                    return Ok(image.into());
+                                }
+                                Some(png_header) => {
+                                    return load_image_bytes(png_header, &bytes, decode_hint, filter, &config, &pool)
+                                        .map(Into::into);
+                                }
+                                None => {}
+                            }
 
-                    let Ok(header) = minipng::decode_png_header(&bytes) else {
-                        return Err(LoadError::NotSupported);
-                    };
+                            // Custom decoders only decode at the original size
+                            let image = decoder.decode(&bytes)?;
+                            let target = target_size(image.size, decode_hint);
+                            Ok(if target == image.size { image } else { downsample(&image, target, filter) }.into())
+                        };
 
-                    let result = load_image_bytes(&header, &bytes).map(Arc::new).map_err(|e| e.to_string());
-                    cache.insert(uri.into(), result.clone());
-                    match result {
-                        Ok(image) => Ok(ImagePoll::Ready { image }),
-                        Err(err) => Err(LoadError::Loading(err)),
+                        let mut start = Instant::now();
+                        let mut result = attempt();
+                        // Background decodes have the time to retry failures that may not happen again
+                        for _ in 0..if background { config.decode_retries } else { 0 } {
+                            if !result.as_ref().is_err_and(PngLoadError::is_transient) || guard.is_cancelled() {
+                                break;
+                            }
+                            std::thread::sleep(config.decode_retry_backoff);
+                            start = Instant::now();
+                            result = attempt();
+                        }
+                        let elapsed = start.elapsed();
+                        metrics.decoded(elapsed);
+                        #[cfg(feature = "log")]
+                        if result.is_ok() {
+                            log::debug!("decoded {uri} at {} in {elapsed:?}", HintLabel(decode_hint));
+                        }
+                        let entry = result.map_err(Arc::new);
+                        // Failures are cached whatever the size of the image, so that they aren't retried every frame,
+                        // except for truncated data, which may be replaced by a complete download
+                        let truncated = entry.as_ref().is_err_and(|err| matches!(**err, PngLoadError::Truncated));
+                        if config.cache_enabled && (cached || entry.is_err()) && !truncated {
+                            let stats = entry.is_ok().then(|| ImageStats {
+                                decode_duration: elapsed,
+                                compressed_size: bytes.len(),
+                                decoded_size: entry_byte_size(&entry),
+                                budget_downscale,
+                            });
+                            let evicted = guard.finish(entry.clone(), fingerprint, stats, config.cache_limits());
+                            config.report_evictions(evicted);
+                        }
+                        config.report_error(&uri, &entry);
+                        entry
                     }
+                };
+
+                if !background {
+                    return poll_entry(&decode());
                 }
-                Ok(BytesPoll::Pending { size }) => Ok(ImagePoll::Pending { size }),
-                Err(err) => Err(err),
+                let ctx = ctx.clone();
+                self.decodes.submit(move || {
+                    // Decodes forgotten while they were queued are skipped altogether
+                    if cancelled.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    // The next frame picks up the result from the cache
+                    let _ = decode();
+                    ctx.request_repaint();
+                });
+                pending()
+            }
+            Ok(BytesPoll::Pending { size }) => {
+                self.config.bytes_pending(ctx);
+                // egui doesn't hand out partial bytes, but a header read earlier, e.g. by `peek_header` or before the
+                // image was invalidated, still gives its true size so that layouts don't jump once it is ready
+                let header = self.headers.lock().get(&*key_uri).copied();
+                let size = header.map_or(size, |header| Some(egui::vec2(header.width as f32, header.height as f32)));
+                Ok(ImagePoll::Pending { size })
             }
+            Err(err) => match self.load_inserted(&key_uri, size_hint) {
+                Some(result) => result,
+                // We never claimed to support this URI, so let other loaders have a go at it
+                None if sniffing => Err(LoadError::NotSupported),
+                None => {
+                    if matches!(err, LoadError::Loading(_)) && !self.config.byte_error_backoff.is_zero() {
+                        self.byte_errors.lock().insert(key_uri.into_owned(), (Instant::now(), err.clone()));
+                    }
+                    Err(err)
+                }
+            },
         }
     }
 
+    /// Returns the error that fetching the bytes of `uri` last failed with, if it is still
+    /// [backing off](PngLoaderBuilder::byte_error_backoff) from it.
+    fn recent_byte_error(&self, uri: &str) -> Option<LoadError> {
+        let mut byte_errors = self.byte_errors.lock();
+        let (failed_at, err) = byte_errors.get(uri)?;
+        if failed_at.elapsed() < self.config.byte_error_backoff {
+            return Some(err.clone());
+        }
+        byte_errors.remove(uri);
+        None
+    }
+
+    /// Loads an image registered with [`Self::insert_bytes`] at `size_hint`, since no byte loader knows about it.
+    fn load_inserted(&self, uri: &str, size_hint: SizeHint) -> Option<ImageLoadResult> {
+        let key = (uri.to_owned(), SizeHint::default());
+        let cached = self.cache.lock().get(&key).cloned();
+        let image = match cached {
+            Some(Ok(image)) => image.color_image(),
+            Some(Err(err)) if self.compressed.lock().contains_key(uri) => {
+                return Some(Err(LoadError::Loading(err.to_string())));
+            }
+            Some(Err(_)) => return None,
+            // Lazily decoded images haven't been decoded yet, and invalidated or evicted ones are decoded again
+            None => {
+                let bytes = self.compressed.lock().get(uri).cloned()?;
+                match self.decode_and_insert(uri, &bytes, None) {
+                    Ok(image) => image.color_image(),
+                    Err(err) => {
+                        // Cache the error like any other failed decode, so that the bytes aren't decoded every frame
+                        let err = Arc::new(err);
+                        let entry = Err(err.clone());
+                        self.config.report_error(uri, &entry);
+                        let evicted = self.cache.lock().insert(key, entry, None, self.config.cache_limits());
+                        self.config.report_evictions(evicted);
+                        return Some(Err(LoadError::Loading(err.to_string())));
+                    }
+                }
+            }
+        };
+        let target = target_size(image.size, size_hint);
+        if target == image.size {
+            return Some(Ok(ImagePoll::Ready { image }));
+        }
+
+        Some(self.insert_derived(
+            (uri.to_owned(), size_hint),
+            downsample(&image, target, self.config.downsample_filter_for(uri)),
+            None,
+        ))
+    }
+
+    /// Returns how many bytes each cached URI accounts for in [`ImageLoader::byte_size`], in no particular order.
+    ///
+    /// Every size of an image, its animation frames and its 16-bit copy all count towards its URI.
+    pub fn entry_sizes(&self) -> Vec<(String, usize)> {
+        let mut sizes: HashMap<String, usize> = HashMap::default();
+        for (key, entry) in self.cache.lock().entries() {
+            *sizes.entry(key.0.clone()).or_default() += slot_byte_size(key, entry);
+        }
+        for (uri, entry) in self.animations.lock().iter() {
+            *sizes.entry(uri.clone()).or_default() += animation_byte_size(uri, entry);
+        }
+        for (uri, image) in self.rgba16.lock().iter() {
+            *sizes.entry(uri.clone()).or_default() += rgba16_byte_size(uri, image);
+        }
+        for uri in self.headers.lock().keys() {
+            *sizes.entry(uri.clone()).or_default() += map_entry_byte_size::<String, PngMetadata>(uri);
+        }
+        for (uri, bytes) in self.compressed.lock().iter() {
+            *sizes.entry(uri.clone()).or_default() += compressed_byte_size(uri, bytes);
+        }
+        sizes.into_iter().collect()
+    }
+
+    /// Returns the error that loading `uri` failed with, if any cached load of it failed.
+    pub fn error(&self, uri: &str) -> Option<Arc<PngLoadError>> {
+        let uri = &*normalize_uri(uri);
+        self.cache.lock().entries().find_map(|((key, _), entry)| match entry {
+            Err(err) if key == uri => Some(err.clone()),
+            _ => None,
+        })
+    }
+
+    /// Returns every image in the cache along with its normalized URI, e.g. for a debug view of everything the
+    /// loader holds.
+    ///
+    /// URIs are listed once for each size they are cached at, in no particular order. Images are shared with the
+    /// cache, except for [compacted palettes](PngLoaderBuilder::compact_palettes), which are expanded to RGBA.
+    pub fn loaded_images(&self) -> Vec<(String, Arc<ColorImage>)> {
+        // Palettes are only expanded once the cache is unlocked
+        let images: Vec<(String, CachedImage)> = self
+            .cache
+            .lock()
+            .entries()
+            .filter_map(|((uri, _), entry)| Some((uri.clone(), entry.as_ref().ok()?.clone())))
+            .collect();
+        images.into_iter().map(|(uri, image)| (uri, image.color_image())).collect()
+    }
+
+    /// Groups the URIs of images whose decoded pixels are identical, e.g. to find assets that ship more than once
+    /// under different names.
+    ///
+    /// Only images cached at their original size are compared, by a hash of their dimensions and pixels, so images
+    /// that only differ in size are never grouped. Derived versions, such as grayscale ones, are left out. Each image
+    /// is hashed once, the first time this is called after it was cached. Groups are sorted, and images without a
+    /// duplicate aren't listed.
+    pub fn find_duplicates(&self) -> Vec<Vec<String>> {
+        let mut groups: HashMap<u64, Vec<String>> = HashMap::default();
+        for (uri, hash) in self.cache.lock().content_hashes() {
+            if strip_derived_fragment(&uri) == uri {
+                groups.entry(hash).or_default().push(uri);
+            }
+        }
+        let mut groups: Vec<Vec<String>> = groups.into_values().filter(|uris| uris.len() > 1).collect();
+        groups.iter_mut().for_each(|uris| uris.sort());
+        groups.sort();
+        groups
+    }
+
+    /// Returns every URI whose cached loads failed, once each and in no particular order, along with the error it
+    /// failed with, as returned by [`Self::error`].
+    pub fn errors(&self) -> Vec<(String, Arc<PngLoadError>)> {
+        let mut errors: HashMap<String, Arc<PngLoadError>> = HashMap::default();
+        for ((uri, _), entry) in self.cache.lock().entries() {
+            if let Err(err) = entry {
+                errors.entry(uri.clone()).or_insert_with(|| err.clone());
+            }
+        }
+        errors.into_iter().collect()
+    }
+}
+
+impl Default for PngLoader {
+    fn default() -> Self {
+        Self::from_config(Config::default(), RandomState::new())
+    }
+}
+
+/// Summarizes the loader's cache on a single line, without listing its images.
+impl<S: BuildHasher + Clone + Send + Sync + 'static> fmt::Debug for PngLoader<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (entries, errors) = {
+            let cache = self.cache.lock();
+            (cache.len(), cache.entries().filter(|(_, entry)| entry.is_err()).count())
+        };
+        let (animations, animation_errors) = {
+            let animations = self.animations.lock();
+            (animations.len(), animations.values().filter(|entry| entry.is_err()).count())
+        };
+        // Measured separately, as it locks the caches again
+        let byte_size = self.byte_size();
+        f.debug_struct("PngLoader")
+            .field("entries", &entries)
+            .field("animations", &animations)
+            .field("errors", &(errors + animation_errors))
+            .field("byte_size", &byte_size)
+            .finish_non_exhaustive()
+    }
+}
+
+fn animation_byte_size(uri: &str, entry: &AnimationEntry) -> usize {
+    let value = match entry {
+        Ok(animation) => {
+            let frames: usize = animation.frames.iter().map(|(frame, _)| image_byte_size(frame)).sum();
+            size_of::<AnimatedPng>() + animation.frames.len() * size_of::<(ColorImage, Duration)>() + frames
+        }
+        Err(_) => size_of::<PngLoadError>(),
+    };
+    map_entry_byte_size::<String, AnimationEntry>(uri) + ARC_OVERHEAD + value
+}
+
+fn rgba16_byte_size(uri: &str, image: &Rgba16Image) -> usize {
+    let value = size_of::<Rgba16Image>() + image.pixels.len() * size_of::<[u16; 4]>();
+    map_entry_byte_size::<String, Arc<Rgba16Image>>(uri) + ARC_OVERHEAD + value
+}
+
+fn compressed_byte_size(uri: &str, bytes: &[u8]) -> usize {
+    map_entry_byte_size::<String, Arc<[u8]>>(uri) + ARC_OVERHEAD + bytes.len()
+}
+
+/// Describes a size hint in log messages, as [`SizeHint`] doesn't implement [`fmt::Debug`].
+#[cfg(feature = "log")]
+struct HintLabel(SizeHint);
+
+#[cfg(feature = "log")]
+impl fmt::Display for HintLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            SizeHint::Scale(factor) => write!(f, "scale {}", factor.into_inner()),
+            SizeHint::Width(width) => write!(f, "width {width}"),
+            SizeHint::Height(height) => write!(f, "height {height}"),
+            SizeHint::Size(width, height) => write!(f, "size {width}x{height}"),
+        }
+    }
+}
+
+fn poll_entry(entry: &Entry) -> ImageLoadResult {
+    match entry {
+        Ok(image) => Ok(ImagePoll::Ready { image: image.color_image() }),
+        Err(err) => Err(LoadError::Loading(err.to_string())),
+    }
+}
+
+impl<S: BuildHasher + Clone + Send + Sync + 'static> ImageLoader for PngLoader<S> {
+    fn id(&self) -> &str {
+        PngLoader::ID
+    }
+
+    /// Loads the image at `uri`, decoding it unless it is already cached at `size_hint`.
+    ///
+    /// egui's texture loader only calls this when it has no texture for `uri`, so an image is never decoded again
+    /// while its texture is still around, and there is nothing to gain from checking for one here.
+    /// [`Context::forget_image`] drops the texture along with the cached image, so the next load has to decode it
+    /// anyway.
+    fn load(&self, ctx: &egui::Context, uri: &str, size_hint: SizeHint) -> ImageLoadResult {
+        self.with_fallback(self.load_image(ctx, uri, size_hint))
+    }
+
+    /// Forgets everything cached for the image at `uri`: every size it was loaded at, along with the images derived
+    /// from it, such as parts of it, its grayscale and linear-space versions and alpha mask, its animation and its
+    /// 16-bit copy.
+    ///
+    /// Background decodes of the image that are still in flight are cancelled, so their results are discarded
+    /// instead of being cached. Those that haven't started yet are skipped.
+    ///
+    /// [`Context::forget_image`] calls this along with egui's bytes and texture loaders, which is the simplest way
+    /// to reload a changed image: the next frame that shows it fetches, decodes and uploads it anew. Until the new
+    /// version is ready, nothing is shown in its place, and a new texture is uploaded even if the bytes turn out
+    /// unchanged.
     fn forget(&self, uri: &str) {
-        let _ = self.cache.lock().remove(uri);
+        let uri = &*normalize_uri(uri);
+        let mut cache = self.cache.lock();
+        cache.retain(|(key, _), _| !belongs_to(key, uri));
+        cache.cancel_decodes(|(key, _)| belongs_to(key, uri));
+        drop(cache);
+        self.animations.lock().remove(uri);
+        self.rgba16.lock().remove(uri);
+        self.headers.lock().remove(uri);
+        self.compressed.lock().remove(uri);
+        self.byte_errors.lock().remove(uri);
     }
 
     fn forget_all(&self) {
         self.cache.lock().clear();
+        self.animations.lock().clear();
+        self.rgba16.lock().clear();
+        self.headers.lock().clear();
+        self.compressed.lock().clear();
+        self.byte_errors.lock().clear();
+        self.pool.clear();
     }
 
+    /// Estimates the memory used by the loader.
+    ///
+    /// Besides pixels, this counts the URIs the images are keyed by, the structs and reference counts wrapping them
+    /// and the entries of the maps holding them. Allocator overhead and unused map capacity aren't known, so the
+    /// result is an approximation that errs on the low side. Maps keep their capacity after images are forgotten,
+    /// so the memory actually reserved may be well above this until [`PngLoader::trim`] is called.
     fn byte_size(&self) -> usize {
-        self.cache
-            .lock()
-            .values()
-            .map(|result| match result {
-                Ok(image) => image.pixels.len() * size_of::<egui::Color32>(),
-                Err(err) => err.len(),
-            })
-            .sum()
+        let animation_bytes: usize =
+            self.animations.lock().iter().map(|(uri, entry)| animation_byte_size(uri, entry)).sum();
+        let rgba16_bytes: usize = self.rgba16.lock().iter().map(|(uri, image)| rgba16_byte_size(uri, image)).sum();
+        let format_bytes: usize =
+            self.headers.lock().keys().map(|uri| map_entry_byte_size::<String, PngMetadata>(uri)).sum();
+        let compressed_bytes: usize =
+            self.compressed.lock().iter().map(|(uri, bytes)| compressed_byte_size(uri, bytes)).sum();
+        self.cache.lock().byte_size()
+            + animation_bytes
+            + rgba16_bytes
+            + format_bytes
+            + compressed_bytes
+            + self.pool.byte_size()
     }
 }
 
-/// Installs the minipng image loader.
-pub fn install(context: &Context) {
-    context.add_image_loader(Arc::new(PngLoader::default()))
+/// Installs the minipng image loader with its default settings.
+///
+/// Returns a handle to the installed loader, which can be kept around to manage its cache. Installing again, e.g. from
+/// separate plugins, returns the same loader rather than adding another. To configure the loader, use
+/// [`install_with_builder`] instead.
+pub fn install(context: &Context) -> Arc<PngLoader> {
+    PngLoader::builder().install(context)
+}
+
+/// Installs a minipng image loader configured by `builder`, returning a handle to it.
+///
+/// This is the recommended way to set up the loader, as it configures and installs it in a single call. For
+/// example, URIs without a `.png` extension, such as those of an asset server, can be loaded by enabling
+/// [content sniffing](PngLoaderBuilder::sniff_content) or accepting their MIME types with
+/// [`PngLoaderBuilder::mime_predicate`]:
+///
+/// ```no_run
+/// # let ctx = egui::Context::default();
+/// let loader = egui_minipng::install_with_builder(&ctx, egui_minipng::PngLoader::builder().sniff_content(true));
+/// ```
+pub fn install_with_builder(context: &Context, builder: PngLoaderBuilder) -> Arc<PngLoader> {
+    builder.install(context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_pngs::*;
+
+    /// Returns a context that serves the bytes of each image under its URI.
+    fn context_with(images: &[(&'static str, &'static [u8])]) -> Context {
+        let ctx = Context::default();
+        for &(uri, bytes) in images {
+            ctx.include_bytes(uri, bytes);
+        }
+        ctx
+    }
+
+    fn is_ready(result: ImageLoadResult) -> bool {
+        matches!(result, Ok(ImagePoll::Ready { .. }))
+    }
+
+    #[test]
+    fn clear_errors_only_forgets_failed_loads() {
+        let ctx = context_with(&[("good.png", RGBA_2X2), ("empty.png", EMPTY_0X1)]);
+        let loader = PngLoader::default();
+        assert!(is_ready(loader.load(&ctx, "good.png", SizeHint::default())));
+        assert!(loader.load(&ctx, "empty.png", SizeHint::default()).is_err());
+        assert_eq!(loader.entry_status("empty.png"), Some(EntryStatus::Failed));
+
+        loader.clear_errors();
+        assert_eq!(loader.entry_status("good.png"), Some(EntryStatus::Loaded));
+        assert_eq!(loader.entry_status("empty.png"), None);
+        assert!(loader.errors().is_empty());
+    }
+
+    #[test]
+    fn truncated_data_is_not_cached() {
+        let ctx = context_with(&[("image.png", RGBA_2X2_TRUNCATED)]);
+        let loader = PngLoader::default();
+        assert!(matches!(loader.load(&ctx, "image.png", SizeHint::default()), Err(LoadError::Loading(_))));
+        assert_eq!(loader.entry_status("image.png"), None);
+
+        // Once the download completes, the image loads
+        ctx.forget_image("image.png");
+        ctx.include_bytes("image.png", RGBA_2X2);
+        assert!(is_ready(loader.load(&ctx, "image.png", SizeHint::default())));
+    }
+
+    /// Loads `a.png`, `b.png` and `c.png` in turn with a loader built by `builder`, and returns which are still cached.
+    fn cached_after_loading_three(builder: PngLoaderBuilder) -> Vec<&'static str> {
+        let ctx = context_with(&[("a.png", RGBA_2X2), ("b.png", RGBA_2X2), ("c.png", RGBA_2X2)]);
+        let loader = builder.build();
+        for uri in ["a.png", "b.png", "c.png"] {
+            assert!(is_ready(loader.load(&ctx, uri, SizeHint::default())));
+        }
+        ["a.png", "b.png", "c.png"].into_iter().filter(|uri| loader.is_cached(uri)).collect()
+    }
+
+    #[test]
+    fn cache_limits_evict_least_recently_used_images() {
+        let entry_bytes = {
+            let ctx = context_with(&[("a.png", RGBA_2X2)]);
+            let loader = PngLoader::default();
+            assert!(is_ready(loader.load(&ctx, "a.png", SizeHint::default())));
+            let bytes = loader.cache.lock().byte_size();
+            bytes
+        };
+        let builder = PngLoader::builder;
+
+        assert_eq!(cached_after_loading_three(builder()), ["a.png", "b.png", "c.png"]);
+        assert_eq!(cached_after_loading_three(builder().max_cache_entries(Some(2))), ["b.png", "c.png"]);
+        assert_eq!(cached_after_loading_three(builder().max_cache_bytes(Some(2 * entry_bytes))), ["b.png", "c.png"]);
+
+        // Whichever limit is exceeded evicts images
+        let both = |entries, bytes| builder().max_cache_entries(Some(entries)).max_cache_bytes(Some(bytes));
+        assert_eq!(cached_after_loading_three(both(2, entry_bytes)), ["c.png"]);
+        assert_eq!(cached_after_loading_three(both(1, 3 * entry_bytes)), ["c.png"]);
+        assert_eq!(cached_after_loading_three(both(3, 3 * entry_bytes)), ["a.png", "b.png", "c.png"]);
+    }
+
+    fn ready_pixels(result: ImageLoadResult) -> Vec<Color32> {
+        match result {
+            Ok(ImagePoll::Ready { image }) => image.pixels.clone(),
+            _ => panic!("the image isn't ready"),
+        }
+    }
+
+    #[test]
+    fn grayscale_versions_are_cached_alongside_the_image() {
+        let ctx = context_with(&[("image.png", RGBA_2X2)]);
+        let loader = PngLoader::default();
+        let half_white = Color32::from_rgba_unmultiplied(255, 255, 255, 128);
+        assert_eq!(
+            ready_pixels(loader.load_grayscale(&ctx, "image.png", SizeHint::default())),
+            [Color32::from_gray(54), Color32::from_gray(182), Color32::from_gray(18), half_white]
+        );
+        assert_eq!(
+            ready_pixels(loader.load(&ctx, "image.png", SizeHint::default())),
+            [Color32::RED, Color32::GREEN, Color32::BLUE, half_white]
+        );
+        assert_eq!(loader.len(), 2);
+
+        loader.forget("image.png");
+        assert!(loader.is_empty());
+    }
+
+    fn ready_size(result: ImageLoadResult) -> [usize; 2] {
+        match result {
+            Ok(ImagePoll::Ready { image }) => image.size,
+            _ => panic!("the image isn't ready"),
+        }
+    }
+
+    #[test]
+    fn forget_drops_every_size_of_an_image() {
+        let ctx = context_with(&[("image.png", RGBA_4X4), ("other.png", RGBA_2X2)]);
+        let loader = PngLoader::default();
+        assert_eq!(ready_size(loader.load(&ctx, "image.png", SizeHint::default())), [4, 4]);
+        assert_eq!(ready_size(loader.load(&ctx, "image.png", SizeHint::Size(2, 2))), [2, 2]);
+        assert!(is_ready(loader.load(&ctx, "other.png", SizeHint::default())));
+        assert_eq!(loader.len(), 3);
+
+        loader.forget("./image.png");
+        assert_eq!(loader.cached_uris(), ["other.png"]);
+    }
+
+    #[test]
+    fn mismatched_crcs_are_only_rejected_when_verified() {
+        let ctx = context_with(&[("image.png", RGBA_2X2_BAD_CRC)]);
+        assert!(is_ready(PngLoader::default().load(&ctx, "image.png", SizeHint::default())));
+
+        let loader = PngLoader::builder().verify_crc(true).build();
+        assert!(loader.load(&ctx, "image.png", SizeHint::default()).is_err());
+        assert!(
+            matches!(loader.error("image.png").as_deref(), Some(PngLoadError::Corrupt { chunk }) if chunk == b"IDAT")
+        );
+    }
+
+    #[test]
+    fn each_scale_of_an_image_is_cached_separately() {
+        let ctx = context_with(&[("image.png", RGBA_4X4)]);
+        let loader = PngLoader::default();
+        for (factor, size) in [(0.5, [2, 2]), (0.25, [1, 1]), (1.0, [4, 4]), (0.5, [2, 2])] {
+            assert_eq!(ready_size(loader.load(&ctx, "image.png", SizeHint::Scale(factor.into()))), size);
+        }
+        assert_eq!(loader.len(), 3);
+    }
+
+    #[test]
+    fn tinted_versions_multiply_the_image_by_their_tint() {
+        let ctx = context_with(&[("image.png", RGBA_2X2)]);
+        let loader = PngLoader::default();
+        let half_white = Color32::from_rgba_unmultiplied(255, 255, 255, 128);
+        assert_eq!(
+            ready_pixels(loader.load_tinted(&ctx, "image.png", Color32::RED, SizeHint::default())),
+            [Color32::RED, Color32::BLACK, Color32::BLACK, Color32::from_rgba_premultiplied(half_white.r(), 0, 0, 128)]
+        );
+        assert_eq!(
+            ready_pixels(loader.load_tinted(&ctx, "image.png", Color32::TRANSPARENT, SizeHint::default())),
+            [Color32::TRANSPARENT; 4]
+        );
+
+        // Each tint is cached separately from the untinted image, and forgotten along with it
+        assert_eq!(loader.len(), 3);
+        loader.forget("image.png");
+        assert!(loader.is_empty());
+    }
+
+    #[test]
+    fn images_over_the_entry_limit_load_without_being_cached() {
+        let ctx = context_with(&[("large.png", RGBA_4X4), ("small.png", RGBA_2X2)]);
+        let loader = PngLoader::builder().max_entry_bytes(Some(32)).build();
+        for _ in 0..2 {
+            assert_eq!(ready_size(loader.load(&ctx, "large.png", SizeHint::default())), [4, 4]);
+        }
+        assert!(!loader.is_cached("large.png"));
+
+        // Downscaled versions that fit are cached
+        assert_eq!(ready_size(loader.load(&ctx, "large.png", SizeHint::Size(2, 2))), [2, 2]);
+        assert!(is_ready(loader.load(&ctx, "small.png", SizeHint::default())));
+        assert_eq!(loader.len(), 2);
+    }
+
+    #[test]
+    fn linear_versions_convert_colors_from_srgb() {
+        let ctx = context_with(&[("image.png", GRAY_4X1)]);
+        let loader = PngLoader::default();
+        assert_eq!(
+            ready_pixels(loader.load_linear(&ctx, "image.png", SizeHint::default())),
+            [0, 13, 55, 255].map(Color32::from_gray)
+        );
+        assert_eq!(
+            ready_pixels(loader.load(&ctx, "image.png", SizeHint::default())),
+            [0, 64, 128, 255].map(Color32::from_gray)
+        );
+    }
+
+    #[test]
+    fn images_over_the_decode_budget_are_downscaled_to_fit() {
+        let ctx = context_with(&[("large.png", RGBA_4X4), ("small.png", RGBA_2X2)]);
+        let loader = PngLoader::builder().max_decoded_bytes_per_load(Some(20)).build();
+        assert_eq!(ready_size(loader.load(&ctx, "large.png", SizeHint::default())), [2, 2]);
+        assert_eq!(loader.stats("large.png").unwrap().budget_downscale, 2);
+
+        assert_eq!(ready_size(loader.load(&ctx, "small.png", SizeHint::default())), [2, 2]);
+        assert_eq!(loader.stats("small.png").unwrap().budget_downscale, 1);
+    }
+
+    #[test]
+    fn alpha_masks_are_white_with_the_alpha_of_the_image() {
+        let ctx = context_with(&[("image.png", RGBA_2X2)]);
+        let loader = PngLoader::default();
+        assert_eq!(
+            ready_pixels(loader.load_alpha_mask(&ctx, "image.png", SizeHint::default())),
+            [255, 255, 255, 128].map(Color32::from_white_alpha)
+        );
+    }
+
+    #[test]
+    fn load_full_bypasses_downscaling() {
+        let ctx = context_with(&[("image.png", RGBA_4X4)]);
+        let loader = PngLoader::builder().max_decoded_bytes_per_load(Some(20)).build();
+        assert_eq!(ready_size(loader.load(&ctx, "image.png", SizeHint::default())), [2, 2]);
+        assert_eq!(ready_size(loader.load_full(&ctx, "image.png")), [4, 4]);
+        assert_eq!(loader.len(), 2);
+
+        loader.forget("image.png");
+        assert!(loader.is_empty());
+    }
+
+    #[test]
+    fn load_full_rejects_and_reports_like_load() {
+        let ctx = context_with(&[("image.txt", RGBA_2X2), ("empty.png", EMPTY_0X1)]);
+        let reported = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let errors = reported.clone();
+        let loader = PngLoader::builder().on_error(move |uri, _| errors.lock().unwrap().push(uri.to_owned())).build();
+        assert!(matches!(loader.load_full(&ctx, "image.txt"), Err(LoadError::NotSupported)));
+
+        for _ in 0..2 {
+            assert!(matches!(loader.load_full(&ctx, "empty.png"), Err(LoadError::Loading(_))));
+        }
+        assert_eq!(loader.entry_status("empty.png"), Some(EntryStatus::Failed));
+        assert_eq!(*reported.lock().unwrap(), ["empty.png"]);
+    }
+
+    #[test]
+    fn read_images_follow_the_cache_settings() {
+        let loader = PngLoader::builder().cache_enabled(false).build();
+        assert_eq!(loader.load_reader("image.png", RGBA_2X2).unwrap().size, [2, 2]);
+        assert!(loader.is_empty());
+
+        let loader = PngLoader::builder().max_entry_bytes(Some(32)).build();
+        assert_eq!(loader.load_reader("large.png", RGBA_4X4).unwrap().size, [4, 4]);
+        assert_eq!(loader.load_reader("small.png", RGBA_2X2).unwrap().size, [2, 2]);
+        assert_eq!(loader.cached_uris(), ["small.png"]);
+    }
+
+    #[test]
+    fn preloading_caches_every_ready_image() {
+        let ctx = context_with(&[("a.png", RGBA_2X2), ("b.png", RGBA_4X4), ("c.png", GRAY_4X1)]);
+        let loader = PngLoader::default();
+        loader.preload(&ctx, &["a.png", "b.png", "c.png", "missing.png"]);
+        assert_eq!(loader.metrics().decodes, 3);
+        for uri in ["a.png", "b.png", "c.png"] {
+            assert_eq!(loader.entry_status(uri), Some(EntryStatus::Loaded));
+        }
+    }
+
+    #[test]
+    fn installing_twice_returns_the_installed_loader() {
+        let ctx = Context::default();
+        let first = install(&ctx);
+        let second = install_with_builder(&ctx, PngLoader::builder().max_cache_entries(Some(1)));
+        assert!(Arc::ptr_eq(&first, &second));
+
+        let image_loaders = ctx.loaders().image.lock().clone();
+        assert_eq!(image_loaders.iter().filter(|loader| loader.id() == PngLoader::ID).count(), 1);
+    }
+
+    /// Decodes anything starting with `solid` into a 3x1 white image.
+    struct SolidDecoder;
+
+    impl Decoder for SolidDecoder {
+        fn header(&self, bytes: &[u8]) -> Result<DecodedHeader, PngLoadError> {
+            if !bytes.starts_with(b"solid") {
+                return Err(PngLoadError::Io(std::io::ErrorKind::InvalidData.into()));
+            }
+            Ok(DecodedHeader { width: 3, height: 1 })
+        }
+
+        fn decode(&self, bytes: &[u8]) -> Result<ColorImage, PngLoadError> {
+            self.header(bytes)?;
+            Ok(ColorImage::new([3, 1], Color32::WHITE))
+        }
+    }
+
+    #[test]
+    fn custom_decoders_replace_minipng() {
+        let ctx = context_with(&[("solid.png", b"solid"), ("image.png", RGBA_2X2)]);
+        let loader = PngLoader::with_decoder(Arc::new(SolidDecoder));
+        assert_eq!(ready_pixels(loader.load(&ctx, "solid.png", SizeHint::default())), [Color32::WHITE; 3]);
+        assert_eq!(ready_size(loader.load_full(&ctx, "solid.png")), [3, 1]);
+        assert!(matches!(loader.load(&ctx, "image.png", SizeHint::default()), Err(LoadError::NotSupported)));
+    }
+
+    /// Panics on its first decode and fails as if the data was cut short on its second, then decodes a 1x1 image.
+    #[derive(Default)]
+    struct FlakyDecoder(std::sync::atomic::AtomicUsize);
+
+    impl Decoder for FlakyDecoder {
+        fn header(&self, _: &[u8]) -> Result<DecodedHeader, PngLoadError> {
+            Ok(DecodedHeader { width: 1, height: 1 })
+        }
+
+        fn decode(&self, _: &[u8]) -> Result<ColorImage, PngLoadError> {
+            match self.0.fetch_add(1, Ordering::Relaxed) {
+                0 => panic!("the decoder crashed"),
+                1 => Err(PngLoadError::Truncated),
+                _ => Ok(ColorImage::new([1, 1], Color32::WHITE)),
+            }
+        }
+    }
+
+    #[test]
+    fn failed_decodes_are_not_left_in_flight() {
+        let ctx = context_with(&[("image.png", b"flaky")]);
+        let loader = PngLoader::with_decoder(Arc::new(FlakyDecoder::default()));
+        let load = || loader.load(&ctx, "image.png", SizeHint::default());
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(load)).is_err());
+        assert!(matches!(load(), Err(LoadError::Loading(_))));
+        assert_eq!(ready_size(load()), [1, 1]);
+    }
+
+    #[test]
+    fn unused_palette_colors_are_not_counted_as_transparency() {
+        let ctx = context_with(&[("image.png", INDEXED_2X1), ("alpha.png", RGBA_2X2)]);
+        for loader in [PngLoader::default(), PngLoader::builder().compact_palettes(true).build()] {
+            assert_eq!(ready_pixels(loader.load(&ctx, "image.png", SizeHint::default())), [Color32::RED; 2]);
+            assert_eq!(loader.has_transparency("image.png"), Some(false));
+            assert!(is_ready(loader.load(&ctx, "alpha.png", SizeHint::default())));
+            assert_eq!(loader.has_transparency("alpha.png"), Some(true));
+        }
+    }
+
+    #[test]
+    fn changed_bytes_are_decoded_again() {
+        let ctx = context_with(&[("image.png", RGBA_2X2)]);
+        let loader = PngLoader::builder().detect_changes(true).build();
+        for _ in 0..2 {
+            assert_eq!(ready_size(loader.load(&ctx, "image.png", SizeHint::default())), [2, 2]);
+        }
+        assert_eq!(loader.metrics().decodes, 1);
+
+        ctx.forget_image("image.png");
+        ctx.include_bytes("image.png", RGBA_4X4);
+        for _ in 0..2 {
+            assert_eq!(ready_size(loader.load(&ctx, "image.png", SizeHint::default())), [4, 4]);
+        }
+        assert_eq!(loader.metrics().decodes, 2);
+    }
+
+    #[test]
+    fn inserted_images_that_fail_to_decode_are_cached_and_reported() {
+        let reported = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let errors = reported.clone();
+        let loader = PngLoader::builder()
+            .lazy_decoding(Some(0))
+            .on_error(move |uri, _| errors.lock().unwrap().push(uri.to_owned()))
+            .build();
+        loader.insert_bytes("image.png", RGBA_2X2_CORRUPT_IDAT).unwrap();
+
+        let ctx = Context::default();
+        for _ in 0..2 {
+            assert!(matches!(loader.load(&ctx, "image.png", SizeHint::default()), Err(LoadError::Loading(_))));
+        }
+        assert!(matches!(loader.load(&ctx, "image.png", SizeHint::Size(1, 1)), Err(LoadError::Loading(_))));
+        assert_eq!(loader.entry_status("image.png"), Some(EntryStatus::Failed));
+        assert_eq!(*reported.lock().unwrap(), ["image.png"]);
+    }
+
+    #[test]
+    fn atlas_regions_are_given_in_pixels_of_the_original_atlas() {
+        let ctx = context_with(&[("atlas.png", RGBA_4X4)]);
+        let loader = PngLoader::builder().max_decoded_bytes_per_load(Some(20)).build();
+        let region = |name: &str, min, max| (name.to_owned(), egui::Rect::from_min_max(min, max));
+        let regions = [
+            region("corner", egui::pos2(2.0, 2.0), egui::pos2(4.0, 4.0)),
+            region("outside", egui::pos2(0.0, 0.0), egui::pos2(5.0, 4.0)),
+        ];
+        let results = loader.load_atlas(&ctx, "atlas.png", &regions).unwrap().unwrap();
+        assert_eq!(results[0].as_ref().unwrap().pixels, [Color32::RED]);
+        assert!(matches!(results[1], Err(PngLoadError::RegionOutOfBounds { width: 4, height: 4, .. })));
+    }
+
+    /// Fails to fetch any bytes, as if the server they came from was down, and counts how often it was asked to.
+    #[derive(Default)]
+    struct FailingBytesLoader(std::sync::atomic::AtomicUsize);
+
+    impl egui::load::BytesLoader for FailingBytesLoader {
+        fn id(&self) -> &str {
+            "failing"
+        }
+
+        fn load(&self, _: &Context, _: &str) -> egui::load::BytesLoadResult {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Err(LoadError::Loading("the server is down".to_owned()))
+        }
+
+        fn forget(&self, _: &str) {}
+
+        fn forget_all(&self) {}
+
+        fn byte_size(&self) -> usize {
+            0
+        }
+    }
+
+    #[test]
+    fn byte_errors_are_only_retried_after_the_backoff() {
+        let ctx = Context::default();
+        let bytes_loader = Arc::new(FailingBytesLoader::default());
+        ctx.add_bytes_loader(bytes_loader.clone());
+        let loader = PngLoader::builder().byte_error_backoff(Duration::from_secs(3600)).build();
+        for _ in 0..3 {
+            assert!(matches!(loader.load(&ctx, "image.png", SizeHint::default()), Err(LoadError::Loading(_))));
+        }
+        assert_eq!(bytes_loader.0.load(Ordering::Relaxed), 1);
+
+        loader.clear_errors();
+        assert!(loader.load(&ctx, "image.png", SizeHint::default()).is_err());
+        assert_eq!(bytes_loader.0.load(Ordering::Relaxed), 2);
+
+        // Without a backoff, every load asks again
+        let loader = PngLoader::default();
+        for _ in 0..2 {
+            assert!(loader.load(&ctx, "image.png", SizeHint::default()).is_err());
+        }
+        assert_eq!(bytes_loader.0.load(Ordering::Relaxed), 4);
+    }
+
+    /// Background decodes waiting to be run.
+    type DecodeJobs = std::sync::Mutex<Vec<Box<dyn FnOnce() + Send>>>;
+
+    /// Returns a builder whose background decodes wait in the returned queue until they are run.
+    fn deferred_decodes() -> (PngLoaderBuilder, Arc<DecodeJobs>) {
+        let queue = Arc::new(DecodeJobs::default());
+        let jobs = queue.clone();
+        (PngLoader::builder().spawner(move |job| jobs.lock().unwrap().push(job)), queue)
+    }
+
+    /// Runs the decodes queued by [`deferred_decodes`], including those that they start in turn.
+    fn run_decodes(queue: &DecodeJobs) {
+        loop {
+            let jobs = std::mem::take(&mut *queue.lock().unwrap());
+            if jobs.is_empty() {
+                break;
+            }
+            jobs.into_iter().for_each(|job| job());
+        }
+    }
+
+    #[test]
+    fn invalidated_images_are_returned_until_they_are_decoded_again() {
+        let ctx = context_with(&[("image.png", RGBA_2X2)]);
+        let (builder, queue) = deferred_decodes();
+        let loader = builder.build();
+        assert!(matches!(loader.load(&ctx, "image.png", SizeHint::default()), Ok(ImagePoll::Pending { .. })));
+        run_decodes(&queue);
+        assert_eq!(ready_size(loader.load(&ctx, "image.png", SizeHint::default())), [2, 2]);
+
+        // The file changes on disk
+        loader.invalidate("image.png");
+        ctx.forget_image("image.png");
+        ctx.include_bytes("image.png", RGBA_4X4);
+        for _ in 0..2 {
+            assert_eq!(ready_size(loader.load(&ctx, "image.png", SizeHint::default())), [2, 2]);
+        }
+        run_decodes(&queue);
+        assert_eq!(ready_pixels(loader.load(&ctx, "image.png", SizeHint::default())), [Color32::RED; 16]);
+    }
+
+    #[test]
+    fn forgetting_finished_or_queued_decodes_is_harmless() {
+        let ctx = context_with(&[("image.png", RGBA_2X2)]);
+        let loader = PngLoader::builder().spawner(|job| job()).build();
+        for _ in 0..2 {
+            // The decode finishes before `load` returns, and the next frame picks it up
+            assert!(matches!(loader.load(&ctx, "image.png", SizeHint::default()), Ok(ImagePoll::Pending { .. })));
+            assert_eq!(ready_size(loader.load(&ctx, "image.png", SizeHint::default())), [2, 2]);
+            loader.forget("image.png");
+        }
+
+        // Decodes that never started are skipped, and don't stop the image from being decoded again
+        let (builder, queue) = deferred_decodes();
+        let loader = builder.build();
+        assert!(matches!(loader.load(&ctx, "image.png", SizeHint::default()), Ok(ImagePoll::Pending { .. })));
+        loader.forget("image.png");
+        run_decodes(&queue);
+        assert!(loader.is_empty());
+        assert_eq!(loader.metrics().decodes, 0);
+        assert!(matches!(loader.load(&ctx, "image.png", SizeHint::default()), Ok(ImagePoll::Pending { .. })));
+        run_decodes(&queue);
+        assert_eq!(ready_size(loader.load(&ctx, "image.png", SizeHint::default())), [2, 2]);
+    }
+
+    /// Fails with `error` on its first `failures` decodes, then decodes a 1x1 image.
+    struct FailingDecoder {
+        failures: usize,
+        error: fn() -> PngLoadError,
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    impl FailingDecoder {
+        fn new(failures: usize, error: fn() -> PngLoadError) -> Arc<Self> {
+            Arc::new(Self { failures, error, calls: Default::default() })
+        }
+    }
+
+    impl Decoder for FailingDecoder {
+        fn header(&self, _: &[u8]) -> Result<DecodedHeader, PngLoadError> {
+            Ok(DecodedHeader { width: 1, height: 1 })
+        }
+
+        fn decode(&self, _: &[u8]) -> Result<ColorImage, PngLoadError> {
+            if self.calls.fetch_add(1, Ordering::Relaxed) < self.failures {
+                return Err((self.error)());
+            }
+            Ok(ColorImage::new([1, 1], Color32::WHITE))
+        }
+    }
+
+    #[test]
+    fn transient_background_failures_are_retried() {
+        let ctx = context_with(&[("image.png", b"failing")]);
+        let out_of_memory = || PngLoadError::Allocation { bytes: 4 };
+        let invalid = || PngLoadError::Io(std::io::ErrorKind::InvalidData.into());
+        let load = |decoder: &Arc<FailingDecoder>| {
+            let loader = PngLoader::builder()
+                .decoder(decoder.clone())
+                .spawner(|job| job())
+                .decode_retries(2, Duration::ZERO)
+                .build();
+            let _ = loader.load(&ctx, "image.png", SizeHint::default());
+            loader.load(&ctx, "image.png", SizeHint::default())
+        };
+
+        let decoder = FailingDecoder::new(2, out_of_memory);
+        assert_eq!(ready_size(load(&decoder)), [1, 1]);
+        assert_eq!(decoder.calls.load(Ordering::Relaxed), 3);
+
+        let decoder = FailingDecoder::new(3, out_of_memory);
+        assert!(matches!(load(&decoder), Err(LoadError::Loading(_))));
+        assert_eq!(decoder.calls.load(Ordering::Relaxed), 3);
+
+        // Failures that come from the image itself aren't retried
+        let decoder = FailingDecoder::new(1, invalid);
+        assert!(matches!(load(&decoder), Err(LoadError::Loading(_))));
+        assert_eq!(decoder.calls.load(Ordering::Relaxed), 1);
+    }
+
+    /// Forgets `image.png` on its loader while decoding it, as if the app moved on mid-decode.
+    #[derive(Default)]
+    struct ForgettingDecoder(std::sync::OnceLock<Arc<PngLoader>>);
+
+    impl Decoder for ForgettingDecoder {
+        fn header(&self, _: &[u8]) -> Result<DecodedHeader, PngLoadError> {
+            Ok(DecodedHeader { width: 1, height: 1 })
+        }
+
+        fn decode(&self, _: &[u8]) -> Result<ColorImage, PngLoadError> {
+            self.0.get().expect("the loader is set").forget("image.png");
+            Ok(ColorImage::new([1, 1], Color32::WHITE))
+        }
+    }
+
+    #[test]
+    fn decodes_forgotten_mid_flight_are_not_cached() {
+        let ctx = context_with(&[("image.png", b"forgotten")]);
+        let decoder = Arc::new(ForgettingDecoder::default());
+        let loader = Arc::new(PngLoader::builder().decoder(decoder.clone()).spawner(|job| job()).build());
+        let _ = decoder.0.set(loader.clone());
+
+        assert!(matches!(loader.load(&ctx, "image.png", SizeHint::default()), Ok(ImagePoll::Pending { .. })));
+        assert_eq!(loader.metrics().decodes, 1);
+        assert!(loader.is_empty());
+    }
+
+    /// Collects the messages logged by every test, which run concurrently, so tests look for their own URIs.
+    #[cfg(feature = "log")]
+    struct TestLogger(std::sync::Mutex<Vec<String>>);
+
+    #[cfg(feature = "log")]
+    impl log::Log for TestLogger {
+        fn enabled(&self, _: &log::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record<'_>) {
+            self.0.lock().unwrap().push(format!("{} {}", record.level(), record.args()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn loads_are_logged() {
+        static LOGGER: TestLogger = TestLogger(std::sync::Mutex::new(Vec::new()));
+        let _ = log::set_logger(&LOGGER);
+        log::set_max_level(log::LevelFilter::Debug);
+
+        let ctx = context_with(&[("logged.png", RGBA_2X2), ("logged_empty.png", EMPTY_0X1)]);
+        let loader = PngLoader::default();
+        for _ in 0..2 {
+            let _ = loader.load(&ctx, "logged.png", SizeHint::default());
+        }
+        let _ = loader.load(&ctx, "logged_empty.png", SizeHint::default());
+
+        let messages = LOGGER.0.lock().unwrap().clone();
+        let logged = |message: &str| messages.iter().any(|logged| logged.starts_with(message));
+        assert!(logged("DEBUG cache miss for logged.png"));
+        assert!(logged("DEBUG decoded logged.png"));
+        assert!(logged("DEBUG cache hit for logged.png"));
+        assert!(logged("WARN failed to load logged_empty.png: "));
+    }
 }
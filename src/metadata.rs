@@ -0,0 +1,118 @@
+use crate::{chunks::chunks, decode::decode_header, PngLoadError};
+use minipng::{BitDepth, ColorType, ImageHeader};
+
+/// Information about a PNG image that can be read from its header without decoding any pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PngMetadata {
+    pub width: u32,
+    pub height: u32,
+    pub color_type: ColorType,
+    pub bit_depth: BitDepth,
+
+    /// Whether the image has an alpha channel.
+    ///
+    /// This is always `false` for indexed images, even if their palette has transparent entries.
+    pub has_alpha: bool,
+
+    /// The horizontal and vertical pixel density in dots per inch, from the `pHYs` chunk.
+    ///
+    /// This is `None` if the image has no `pHYs` chunk, or if it only specifies the aspect ratio of pixels. It is
+    /// never filled in when converting from an [`ImageHeader`], which doesn't contain it.
+    pub dpi: Option<(f32, f32)>,
+}
+
+impl From<&ImageHeader> for PngMetadata {
+    fn from(header: &ImageHeader) -> Self {
+        Self {
+            width: header.width(),
+            height: header.height(),
+            color_type: header.color_type(),
+            bit_depth: header.bit_depth(),
+            has_alpha: matches!(header.color_type(), ColorType::GrayAlpha | ColorType::Rgba),
+            dpi: None,
+        }
+    }
+}
+
+const METERS_PER_INCH: f32 = 0.0254;
+
+/// Reads the pixel density from the `pHYs` chunk, which must come before the image data.
+fn read_dpi(bytes: &[u8]) -> Option<(f32, f32)> {
+    for chunk in chunks(bytes).ok()? {
+        let chunk = chunk.ok()?;
+        match &chunk.kind {
+            // Densities are given in pixels per meter with unit 1, while unit 0 only gives the aspect ratio
+            b"pHYs" if chunk.data.len() == 9 && chunk.data[8] == 1 => {
+                let x = u32::from_be_bytes(chunk.data[0..4].try_into().unwrap());
+                let y = u32::from_be_bytes(chunk.data[4..8].try_into().unwrap());
+                return Some((x as f32 * METERS_PER_INCH, y as f32 * METERS_PER_INCH));
+            }
+            b"IDAT" => break,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Reads the metadata of a PNG image from its header and the chunks that precede its pixels.
+///
+/// The image data itself is never read nor decompressed, so this is very fast.
+pub fn read_metadata(bytes: &[u8]) -> Result<PngMetadata, PngLoadError> {
+    let metadata = PngMetadata::from(&decode_header(bytes)?);
+    Ok(PngMetadata { dpi: read_dpi(bytes), ..metadata })
+}
+
+/// Reads the keyword and text of every `tEXt` and `iTXt` chunk of a PNG image, in the order they appear in.
+///
+/// Latin-1 keywords and `tEXt` text are converted to UTF-8. Compressed text can't be inflated without decoding the
+/// image, so `zTXt` chunks and compressed `iTXt` chunks are skipped, as are malformed ones. Text after a malformed
+/// chunk isn't read either. The pixels aren't decoded, but text chunks may come after them, so the whole file is scanned.
+pub fn read_text_metadata(bytes: &[u8]) -> Result<Vec<(String, String)>, PngLoadError> {
+    decode_header(bytes)?;
+    let mut text = Vec::new();
+    for chunk in chunks(bytes)?.map_while(Result::ok) {
+        match &chunk.kind {
+            b"tEXt" => {
+                if let Some((keyword, value)) = split_null(chunk.data) {
+                    text.push((latin1(keyword), latin1(value)));
+                }
+            }
+            b"iTXt" => text.extend(read_itxt(chunk.data)),
+            _ => {}
+        }
+    }
+    Ok(text)
+}
+
+/// Converts Latin-1 text, which keywords and `tEXt` chunks are written in, to UTF-8.
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| char::from(byte)).collect()
+}
+
+fn split_null(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let null = data.iter().position(|&byte| byte == 0)?;
+    Some((&data[..null], &data[null + 1..]))
+}
+
+/// Reads an uncompressed `iTXt` chunk, which is laid out as the keyword, a compression flag and method, the language
+/// tag and the translated keyword, followed by the text. Only the last two are UTF-8.
+fn read_itxt(data: &[u8]) -> Option<(String, String)> {
+    let (keyword, rest) = split_null(data)?;
+    let [0, _, rest @ ..] = rest else {
+        return None;
+    };
+    let (_language, rest) = split_null(rest)?;
+    let (_translated_keyword, value) = split_null(rest)?;
+    Some((latin1(keyword), String::from_utf8(value.to_vec()).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_pngs::*;
+
+    #[test]
+    fn itxt_keywords_are_latin1() {
+        assert_eq!(read_text_metadata(RGBA_2X2_ITXT).unwrap(), [("Café".to_owned(), "thé".to_owned())]);
+    }
+}
@@ -0,0 +1,88 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// A snapshot of a loader's cache and decode statistics, as returned by [`PngLoader::metrics`](crate::PngLoader::metrics).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PngLoaderMetrics {
+    /// Loads that were served from the cache.
+    pub hits: u64,
+
+    /// Loads that had to decode the image.
+    pub misses: u64,
+
+    /// Images that were decoded, successfully or not.
+    pub decodes: u64,
+
+    /// Total time spent decoding images, in nanoseconds.
+    pub total_decode_nanos: u64,
+}
+
+impl PngLoaderMetrics {
+    /// Returns the share of loads that were served from the cache, or `None` if nothing was loaded yet.
+    pub fn hit_rate(&self) -> Option<f64> {
+        let total = self.hits + self.misses;
+        (total > 0).then(|| self.hits as f64 / total as f64)
+    }
+
+    /// Returns the average time spent decoding an image, or `None` if nothing was decoded yet.
+    pub fn average_decode_time(&self) -> Option<Duration> {
+        (self.decodes > 0).then(|| Duration::from_nanos(self.total_decode_nanos / self.decodes))
+    }
+}
+
+/// How the image cached under a URI was last decoded, as returned by [`PngLoader::stats`](crate::PngLoader::stats).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageStats {
+    /// How long decoding the image took, including any downscaling.
+    pub decode_duration: Duration,
+
+    /// The size of the PNG bytes the image was decoded from.
+    pub compressed_size: usize,
+
+    /// The size of the decoded image, as counted by [`ImageLoader::byte_size`](egui::load::ImageLoader::byte_size).
+    pub decoded_size: usize,
+
+    /// The factor that the image was downscaled by to fit in
+    /// [`PngLoaderBuilder::max_decoded_bytes_per_load`](crate::PngLoaderBuilder::max_decoded_bytes_per_load), or 1
+    /// if it wasn't.
+    pub budget_downscale: usize,
+}
+
+impl ImageStats {
+    /// Returns how many times larger the decoded image is than its PNG bytes.
+    pub fn compression_ratio(&self) -> f64 {
+        self.decoded_size as f64 / self.compressed_size.max(1) as f64
+    }
+}
+
+/// Lock-free counters backing [`PngLoaderMetrics`].
+#[derive(Default)]
+pub(crate) struct Metrics {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    decodes: AtomicU64,
+    total_decode_nanos: AtomicU64,
+}
+
+impl Metrics {
+    pub fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn decoded(&self, elapsed: Duration) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        self.decodes.fetch_add(1, Ordering::Relaxed);
+        self.total_decode_nanos.fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> PngLoaderMetrics {
+        PngLoaderMetrics {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            decodes: self.decodes.load(Ordering::Relaxed),
+            total_decode_nanos: self.total_decode_nanos.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use crate::{cache::CachedImage, PngLoader};
+use egui::{load::SizeHint, Color32, ColorImage};
+use std::{fs, hash::BuildHasher, io, path::Path};
+
+/// The name of the file that the cache is saved to, inside the directory given to [`PngLoader::save_cache`].
+const FILE_NAME: &str = "egui_minipng.cache";
+
+/// Identifies cache files, followed by the version of their format.
+const MAGIC: [u8; 4] = *b"EMPC";
+const VERSION: u32 = 1;
+
+/// A 64-bit FNV-1a hash, which unlike the cache's hasher is guaranteed to stay the same across builds.
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ u64::from(byte)).wrapping_mul(0x100_0000_01b3))
+}
+
+/// A cursor over the bytes of a cache file, which yields `None` once they run out.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.0.len() {
+            return None;
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+}
+
+fn write_entry(out: &mut Vec<u8>, uri: &str, fingerprint: Option<u64>, image: &ColorImage) {
+    let mut body = Vec::with_capacity(4 + uri.len() + 17 + image.pixels.len() * 4);
+    body.extend_from_slice(&(uri.len() as u32).to_le_bytes());
+    body.extend_from_slice(uri.as_bytes());
+    body.push(fingerprint.is_some().into());
+    body.extend_from_slice(&fingerprint.unwrap_or_default().to_le_bytes());
+    body.extend_from_slice(&(image.size[0] as u32).to_le_bytes());
+    body.extend_from_slice(&(image.size[1] as u32).to_le_bytes());
+    body.extend(image.pixels.iter().flat_map(|pixel| pixel.to_array()));
+
+    out.extend_from_slice(&checksum(&body).to_le_bytes());
+    out.extend_from_slice(&(body.len() as u64).to_le_bytes());
+    out.extend_from_slice(&body);
+}
+
+fn read_entry(body: &[u8]) -> Option<(String, Option<u64>, ColorImage)> {
+    let mut reader = Reader(body);
+    let uri_len = reader.u32()? as usize;
+    let uri = String::from_utf8(reader.bytes(uri_len)?.to_vec()).ok()?;
+    let has_fingerprint = reader.bytes(1)?[0] != 0;
+    let fingerprint = reader.u64()?;
+    let size = [reader.u32()? as usize, reader.u32()? as usize];
+    let pixels = reader.bytes(size[0].checked_mul(size[1])?.checked_mul(4)?)?;
+    if !reader.0.is_empty() {
+        return None;
+    }
+
+    let pixels = pixels.chunks_exact(4).map(|p| Color32::from_rgba_premultiplied(p[0], p[1], p[2], p[3])).collect();
+    Some((uri, has_fingerprint.then_some(fingerprint), ColorImage { size, pixels }))
+}
+
+impl<S: BuildHasher + Clone + Send + Sync + 'static> PngLoader<S> {
+    /// Saves every successfully decoded full-size image in the cache to a file in `dir`, so that they can be
+    /// restored with [`Self::load_cache`] the next time the app starts instead of being decoded again.
+    ///
+    /// Images are saved as raw pixels, so the file is much larger than the PNGs it was decoded from. Smaller sizes of
+    /// the images, animations and 16-bit copies aren't saved. An existing cache file is replaced.
+    pub fn save_cache(&self, dir: &Path) -> io::Result<()> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        for ((uri, size_hint), entry, fingerprint) in self.cache.lock().fingerprinted_entries() {
+            if let (Ok(image), true) = (entry, *size_hint == SizeHint::default()) {
+                write_entry(&mut out, uri, fingerprint, &image.color_image());
+            }
+        }
+
+        // Writing to a temporary file first means that a crash never leaves a half-written cache behind
+        let temp = dir.join(format!("{FILE_NAME}.tmp"));
+        fs::create_dir_all(dir)?;
+        fs::write(&temp, out)?;
+        fs::rename(temp, dir.join(FILE_NAME))
+    }
+
+    /// Restores the images saved to `dir` by [`Self::save_cache`] into the cache, and returns how many were restored.
+    ///
+    /// Entries that are corrupt are skipped, as are files written by an incompatible version of this crate. Restored
+    /// images are trusted to still match their URIs, unless the loader
+    /// [detects changes](crate::PngLoaderBuilder::detect_changes), in which case images whose bytes changed since
+    /// they were saved are decoded again.
+    pub fn load_cache(&self, dir: &Path) -> io::Result<usize> {
+        let file = fs::read(dir.join(FILE_NAME))?;
+        let mut reader = Reader(&file);
+        if reader.bytes(4) != Some(&MAGIC[..]) || reader.u32() != Some(VERSION) {
+            return Ok(0);
+        }
+
+        let mut restored = 0;
+        while let (Some(expected), Some(len)) = (reader.u64(), reader.u64()) {
+            let Some(body) = usize::try_from(len).ok().and_then(|len| reader.bytes(len)) else {
+                break;
+            };
+            let Some((uri, fingerprint, image)) = read_entry(body).filter(|_| checksum(body) == expected) else {
+                continue;
+            };
+            let key = (uri, SizeHint::default());
+            let limits = self.config.cache_limits();
+            let evicted = self.cache.lock().insert(key, Ok(CachedImage::from(image)), fingerprint, limits);
+            self.config.report_evictions(evicted);
+            restored += 1;
+        }
+        Ok(restored)
+    }
+}
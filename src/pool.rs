@@ -0,0 +1,55 @@
+use crate::PngLoadError;
+use egui::mutex::Mutex;
+
+/// The number of buffers kept around, enough for a few decodes running at once.
+const MAX_BUFFERS: usize = 4;
+
+/// Scratch buffers recycled across decodes, so that decoding many similarly sized images doesn't allocate a new
+/// buffer for each of them.
+///
+/// A disabled pool allocates a fresh buffer every time and drops buffers as soon as they are returned.
+#[derive(Default)]
+pub(crate) struct BufferPool {
+    enabled: bool,
+    buffers: Mutex<Vec<Vec<u8>>>,
+}
+
+impl BufferPool {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled, buffers: Default::default() }
+    }
+
+    /// Takes a zeroed buffer of `len` bytes, reusing the largest pooled buffer if there is one.
+    ///
+    /// Fails gracefully instead of aborting if there isn't enough memory.
+    pub fn take(&self, len: usize) -> Result<Vec<u8>, PngLoadError> {
+        let mut buffer = if self.enabled { self.buffers.lock().pop() } else { None }.unwrap_or_default();
+        buffer.clear();
+        buffer.try_reserve_exact(len).map_err(|_| PngLoadError::Allocation { bytes: len })?;
+        buffer.resize(len, 0);
+        Ok(buffer)
+    }
+
+    /// Returns a buffer to the pool, keeping only the largest ones.
+    pub fn give(&self, buffer: Vec<u8>) {
+        if !self.enabled {
+            return;
+        }
+
+        let mut buffers = self.buffers.lock();
+        buffers.push(buffer);
+        buffers.sort_unstable_by_key(Vec::capacity);
+        if buffers.len() > MAX_BUFFERS {
+            buffers.remove(0);
+        }
+    }
+
+    pub fn clear(&self) {
+        self.buffers.lock().clear();
+    }
+
+    /// Returns the memory held by pooled buffers, in bytes.
+    pub fn byte_size(&self) -> usize {
+        self.buffers.lock().iter().map(Vec::capacity).sum()
+    }
+}
@@ -0,0 +1,66 @@
+use crate::builder::Config;
+use egui::mutex::Mutex;
+use std::{collections::VecDeque, sync::Arc};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Runs background decodes with at most [`Config::max_concurrent_decodes`] of them at once, queueing the rest.
+///
+/// Queued decodes wait without a thread of their own, and each finished decode starts the next one in line.
+pub(crate) struct DecodeQueue {
+    config: Arc<Config>,
+    state: Mutex<QueueState>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    running: usize,
+    queued: VecDeque<Job>,
+}
+
+/// Starts the next queued decode when dropped, so that one that panics doesn't hold up the others.
+struct Running(Arc<DecodeQueue>);
+
+impl DecodeQueue {
+    pub fn new(config: Arc<Config>) -> Self {
+        Self { config, state: Default::default() }
+    }
+
+    /// Runs `job` through [`Config::spawn`] right away if few enough decodes are running, or once enough of them
+    /// have finished otherwise.
+    pub fn submit(self: &Arc<Self>, job: impl FnOnce() + Send + 'static) {
+        {
+            let mut state = self.state.lock();
+            if state.running >= self.config.max_concurrent_decodes.max(1) {
+                state.queued.push_back(Box::new(job));
+                return;
+            }
+            state.running += 1;
+        }
+        self.run(Box::new(job));
+    }
+
+    fn run(self: &Arc<Self>, job: Job) {
+        let running = Running(self.clone());
+        self.config.spawn(move || {
+            let _running = running;
+            job();
+        });
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let next = {
+            let mut state = self.0.state.lock();
+            let next = state.queued.pop_front();
+            if next.is_none() {
+                state.running -= 1;
+            }
+            next
+        };
+        if let Some(next) = next {
+            self.0.run(next);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use crate::{decode_png_to_color_image, PngLoadError};
+use egui::{ColorImage, Context, Response, TextureHandle, TextureId, TextureOptions, Ui, Vec2};
+
+/// A PNG image decoded once and uploaded straight to a texture, without going through URIs or the loader's cache.
+///
+/// This mirrors `egui_extras::RetainedImage`, for apps that hold on to their images themselves. The texture is freed
+/// once the `RetainedPng` and every clone of its [`TextureHandle`] are dropped.
+#[derive(Clone)]
+pub struct RetainedPng {
+    size: [usize; 2],
+    texture: TextureHandle,
+}
+
+impl RetainedPng {
+    /// Decodes `bytes` and uploads them to a texture named `debug_name`, with the default texture options.
+    pub fn from_bytes(ctx: &Context, debug_name: impl Into<String>, bytes: &[u8]) -> Result<Self, PngLoadError> {
+        Ok(Self::from_color_image(ctx, debug_name, decode_png_to_color_image(bytes)?, TextureOptions::default()))
+    }
+
+    /// Uploads an already decoded `image` to a texture named `debug_name`, drawn with `options`.
+    pub fn from_color_image(
+        ctx: &Context,
+        debug_name: impl Into<String>,
+        image: ColorImage,
+        options: TextureOptions,
+    ) -> Self {
+        let size = image.size;
+        Self { size, texture: ctx.load_texture(debug_name, image, options) }
+    }
+
+    /// The size of the image in pixels.
+    pub fn size(&self) -> [usize; 2] {
+        self.size
+    }
+
+    /// The size of the image in pixels, as a [`Vec2`].
+    pub fn size_vec2(&self) -> Vec2 {
+        egui::vec2(self.size[0] as f32, self.size[1] as f32)
+    }
+
+    pub fn texture_id(&self) -> TextureId {
+        self.texture.id()
+    }
+
+    pub fn texture(&self) -> &TextureHandle {
+        &self.texture
+    }
+
+    /// Shows the image at its original size, in points.
+    pub fn show(&self, ui: &mut Ui) -> Response {
+        self.show_size(ui, self.size_vec2())
+    }
+
+    /// Shows the image scaled down to fit within `max_size`, keeping its aspect ratio. It is never scaled up.
+    pub fn show_max_size(&self, ui: &mut Ui, max_size: Vec2) -> Response {
+        let size = self.size_vec2();
+        let scale = (max_size.x / size.x).min(max_size.y / size.y).min(1.0);
+        self.show_size(ui, size * scale)
+    }
+
+    /// Shows the image stretched to `size`.
+    pub fn show_size(&self, ui: &mut Ui, size: Vec2) -> Response {
+        ui.add(egui::Image::new((self.texture.id(), size)))
+    }
+}
@@ -0,0 +1,190 @@
+use crate::builder::Config;
+use egui::Color32;
+use std::borrow::Cow;
+
+pub(crate) fn is_supported_uri(uri: &str, config: &Config) -> bool {
+    if let Some(data) = uri.strip_prefix("data:") {
+        // data:[<mime>][;base64],<payload>
+        let mime = data.split([';', ',']).next().unwrap_or_default();
+        return is_png_mime(mime, config);
+    }
+
+    // Strip any query string or fragment, e.g. `sprite.png?v=3` or `sprite.png#frag`
+    let path = uri.split(['?', '#']).next().unwrap_or_default();
+    let Some(ext) = extension(path) else {
+        return false;
+    };
+
+    ext.eq_ignore_ascii_case("png")
+        || ext.eq_ignore_ascii_case("apng")
+        || config.extensions.iter().any(|extra| ext.eq_ignore_ascii_case(extra))
+}
+
+/// Returns the extension of the last segment of `path`, following the same rules as
+/// [`std::path::Path::extension`] but without going through the platform's path handling.
+fn extension(path: &str) -> Option<&str> {
+    let name = path.rsplit(['/', '\\']).next()?;
+    match name.rsplit_once('.') {
+        // Names like `.png` are hidden files without an extension
+        Some((stem, ext)) if !stem.is_empty() => Some(ext),
+        _ => None,
+    }
+}
+
+/// Mime types used for PNG and APNG images.
+const PNG_MIMES: [&str; 4] = ["image/png", "image/x-png", "image/apng", "image/vnd.mozilla.apng"];
+
+/// Returns whether bytes served with `mime` may be decoded as a PNG.
+///
+/// Bytes without a mime type are only accepted outside of [strict mode](crate::PngLoaderBuilder::strict_mime).
+pub(crate) fn accepts_mime(mime: Option<&str>, config: &Config) -> bool {
+    match mime {
+        Some(mime) => is_png_mime(mime, config),
+        None => !config.strict_mime,
+    }
+}
+
+pub(crate) fn is_png_mime(mime: &str, config: &Config) -> bool {
+    // Ignore any parameters, e.g. `image/png; charset=binary`
+    let essence = mime.split(';').next().unwrap_or_default().trim();
+    PNG_MIMES.iter().any(|png_mime| essence.eq_ignore_ascii_case(png_mime))
+        || config.mime_predicate.as_ref().is_some_and(|predicate| predicate(mime))
+}
+
+/// Splits a URI with a `#rect=x,y,w,h` fragment into the URI of the whole image and the requested rectangle.
+///
+/// Returns `None` for URIs without such a fragment, and no rectangle if the fragment is malformed.
+pub(crate) fn split_rect_fragment(uri: &str) -> Option<(&str, Option<[usize; 4]>)> {
+    let (source, fragment) = uri.rsplit_once('#')?;
+    let rect = fragment.strip_prefix("rect=")?;
+
+    let mut values = rect.split(',').map(|value| value.trim().parse::<usize>().ok());
+    let rect = match [values.next(), values.next(), values.next(), values.next(), values.next()] {
+        [Some(Some(x)), Some(Some(y)), Some(Some(width)), Some(Some(height)), None] => Some([x, y, width, height]),
+        _ => None,
+    };
+    Some((source, rect))
+}
+
+/// Appended to the URI of an image to key its grayscale version in the cache.
+pub(crate) const GRAYSCALE_FRAGMENT: &str = "#grayscale";
+
+/// Appended to the URI of an image to key its linear-space version in the cache.
+pub(crate) const LINEAR_FRAGMENT: &str = "#linear";
+
+/// Appended to the URI of an image to key its alpha mask in the cache.
+pub(crate) const ALPHA_MASK_FRAGMENT: &str = "#alpha";
+
+/// Appended to the URI of an image to key it at its original size, when the loader would downscale it otherwise.
+pub(crate) const ORIGINAL_FRAGMENT: &str = "#original";
+
+/// Appended to the URI of an image, followed by the tint in hex, to key a tinted version of it in the cache.
+const TINT_FRAGMENT: &str = "#tint=";
+
+/// Returns the fragment appended to the URI of an image to key a version of it tinted with `tint`.
+pub(crate) fn tint_fragment(tint: Color32) -> String {
+    format!("{TINT_FRAGMENT}{}", tint.to_hex())
+}
+
+/// Appended to the URI of an atlas image, followed by the name of a region, to key that region in the cache.
+const REGION_FRAGMENT: &str = "#region=";
+
+/// Returns the cache key of the region called `name` of the atlas image at `uri`.
+pub(crate) fn region_key(uri: &str, name: &str) -> String {
+    format!("{}{REGION_FRAGMENT}{name}", normalize_uri(uri))
+}
+
+/// Returns the URI of the image that the cache key `key` was derived from, if it is of a grayscale, linear-space or
+/// tinted version of one, of its alpha mask, of its original size or of a region of it as an atlas.
+pub(crate) fn strip_derived_fragment(key: &str) -> &str {
+    let key = [GRAYSCALE_FRAGMENT, LINEAR_FRAGMENT, ALPHA_MASK_FRAGMENT, ORIGINAL_FRAGMENT]
+        .into_iter()
+        .find_map(|fragment| key.strip_suffix(fragment))
+        .unwrap_or(key);
+    // Region names come last and may contain anything, so they are cut off where the fragment starts
+    let key = key.find(REGION_FRAGMENT).map_or(key, |start| &key[..start]);
+    key.rfind(TINT_FRAGMENT).map_or(key, |start| &key[..start])
+}
+
+/// Returns whether the cache key `key` belongs to the image at the normalized `uri`: either the image itself, or an
+/// image derived from it, such as a part of a sprite sheet or its grayscale version.
+pub(crate) fn belongs_to(key: &str, uri: &str) -> bool {
+    key.strip_prefix(uri).is_some_and(|rest| rest.is_empty() || rest.starts_with('#'))
+}
+
+/// Normalizes `uri` so that different spellings of the same resource share one cache entry.
+///
+/// This is deliberately conservative, so that different resources never end up with the same key: only plain paths
+/// have their leading `./` stripped and repeated slashes collapsed, and an empty query string is dropped from any
+/// URI other than a `data:` URI.
+pub(crate) fn normalize_uri(uri: &str) -> Cow<'_, str> {
+    if uri.starts_with("data:") {
+        return Cow::Borrowed(uri);
+    }
+
+    let (path, mut suffix) = uri.split_at(uri.find(['?', '#']).unwrap_or(uri.len()));
+    if let Some(rest) = suffix.strip_prefix('?').filter(|rest| rest.is_empty() || rest.starts_with('#')) {
+        suffix = rest;
+    }
+
+    // Anything with a scheme is left to whatever resolves it
+    let path = if path.contains("://") { Cow::Borrowed(path) } else { normalize_path(path) };
+    if matches!(path, Cow::Borrowed(_)) && path.len() + suffix.len() == uri.len() {
+        return Cow::Borrowed(uri);
+    }
+    Cow::Owned(format!("{path}{suffix}"))
+}
+
+fn normalize_path(mut path: &str) -> Cow<'_, str> {
+    while let Some(rest) = path.strip_prefix("./") {
+        path = rest;
+    }
+
+    // Leading slashes are kept as they are, since `//server/share` paths are not the same as `/server/share`
+    let start = path.len() - path.trim_start_matches('/').len();
+    if !path[start..].contains("//") {
+        return Cow::Borrowed(path);
+    }
+
+    let mut normalized = String::with_capacity(path.len());
+    normalized.push_str(&path[..start]);
+    let mut after_slash = false;
+    for c in path[start..].chars() {
+        if !(c == '/' && after_slash) {
+            normalized.push(c);
+        }
+        after_slash = c == '/';
+    }
+    Cow::Owned(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_extension_case_insensitively_and_ignores_query_strings() {
+        let config = Config::default();
+        for uri in ["logo.png", "logo.PNG", "logo.Png", "foo.png?x=1", "foo.png#frag", "https://a.b/c.png?v=3#x"] {
+            assert!(is_supported_uri(uri, &config), "{uri}");
+        }
+        for uri in ["logo.jpg", "png", ".png", "foo.png.txt", "foo?x=.png", "dir.png/file"] {
+            assert!(!is_supported_uri(uri, &config), "{uri}");
+        }
+    }
+
+    #[test]
+    fn accepts_apng_extensions_and_mimes() {
+        let config = Config::default();
+        assert!(is_supported_uri("anim.apng", &config));
+        assert!(is_supported_uri("anim.APNG?v=2", &config));
+        assert!(is_supported_uri("data:image/apng;base64,AAAA", &config));
+
+        for mime in ["image/png", "image/apng", "image/vnd.mozilla.apng", "IMAGE/APNG; charset=binary"] {
+            assert!(accepts_mime(Some(mime), &config), "{mime}");
+        }
+        for mime in ["image/jpeg", "image/apngx", "text/png"] {
+            assert!(!accepts_mime(Some(mime), &config), "{mime}");
+        }
+    }
+}